@@ -0,0 +1,144 @@
+use std::path::Path;
+use crate::Position;
+pub const USAGE: &str = "\
+Usage: hecto [OPTIONS] [+LINE] [FILE[:LINE[:COLUM]]]...
+
+Opens every FILE in a buffer of its own, - reads standard input instead.
++LINE and :LINE:COLUM put the cursor there, lines and colums count from 1.
+
+Options:
+  -R, --readonly  refuse to change or save the opened files
+  -h, --help      print this help and exit
+  -V, --version   print the version and exit
+      --          take the arguments after it as file names";
+//a file named on the command line, with where the cursor should start in it
+pub struct FileArg {
+    pub name: String,
+    pub position: Option<Position>,
+}
+//what to open and how
+#[derive(Default)]
+pub struct Args {
+    pub files: Vec<FileArg>,
+    pub readonly: bool,
+}
+pub enum Command {
+    Edit(Args),
+    Help,
+    Version,
+}
+impl FileArg {
+    pub fn is_stdin(&self) -> bool {
+        self.name == "-"
+    }
+}
+//parses the arguments that follow the program name. +LINE applies to the file after it
+pub fn parse<I: IntoIterator<Item = String>>(arguments: I) -> Result<Command, String> {
+    let mut args = Args::default();
+    let mut line = None;
+    let mut only_files = false;
+    for argument in arguments {
+        if only_files || argument == "-" {
+            args.files.push(file_arg(argument, line.take()));
+        } else if argument.starts_with('-') {
+            match argument.as_str() {
+                "--" => only_files = true,
+                "-R" | "--readonly" => args.readonly = true,
+                "-h" | "--help" => return Ok(Command::Help),
+                "-V" | "--version" => return Ok(Command::Version),
+                _ => return Err(format!("unknown option {argument}")),
+            }
+        } else if let Some(number) = argument.strip_prefix('+') {
+            let number = number.parse::<usize>().map_err(|_| format!("invalid line number {argument}"))?;
+            line = Some(number);
+        } else {
+            args.files.push(file_arg(argument, line.take()));
+        }
+    }
+    if line.is_some() {
+        return Err("+LINE has to come before a file".to_string());
+    }
+    Ok(Command::Edit(args))
+}
+//a trailing :LINE or :LINE:COLUM is taken for a location, unless a file
+//with the whole name exists
+fn file_arg(name: String, line: Option<usize>) -> FileArg {
+    let at_line = |line: usize| Position { x: 0, y: line.saturating_sub(1) };
+    if name == "-" || Path::new(&name).exists() {
+        return FileArg { name, position: line.map(at_line) };
+    }
+    let mut rest = name.as_str();
+    let mut numbers = Vec::new();
+    while numbers.len() < 2 {
+        let Some((before, number)) = rest.rsplit_once(':') else {
+            break;
+        };
+        let Ok(number) = number.parse::<usize>() else {
+            break;
+        };
+        numbers.insert(0, number);
+        rest = before;
+    }
+    match numbers[..] {
+        [line] if !rest.is_empty() => FileArg { name: rest.to_string(), position: Some(at_line(line)) },
+        [line, colum] if !rest.is_empty() => FileArg {
+            name: rest.to_string(),
+            position: Some(Position { x: colum.saturating_sub(1), y: line.saturating_sub(1) }),
+        },
+        _ => FileArg { name, position: line.map(at_line) },
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn parse_args(arguments: &[&str]) -> Result<Command, String> {
+        parse(arguments.iter().map(ToString::to_string))
+    }
+    fn files(arguments: &[&str]) -> Vec<(String, Option<Position>)> {
+        match parse_args(arguments) {
+            Ok(Command::Edit(args)) => args.files.into_iter().map(|file| (file.name, file.position)).collect(),
+            _ => panic!("{arguments:?} did not parse to files"),
+        }
+    }
+    fn at(y: usize, x: usize) -> Position {
+        Position { x, y }
+    }
+    #[test]
+    fn opens_files_at_lines() {
+        assert_eq!(
+            files(&["a.rs", "+12", "b.rs", "c.rs"]),
+            [("a.rs".to_string(), None), ("b.rs".to_string(), Some(at(11, 0))), ("c.rs".to_string(), None)]
+        );
+        assert_eq!(files(&["+0", "a.rs"]), [("a.rs".to_string(), Some(at(0, 0)))]);
+    }
+    #[test]
+    fn opens_files_at_locations() {
+        assert_eq!(files(&["src/a.rs:3"]), [("src/a.rs".to_string(), Some(at(2, 0)))]);
+        assert_eq!(files(&["src/a.rs:3:7"]), [("src/a.rs".to_string(), Some(at(2, 6)))]);
+        assert_eq!(files(&["+5", "a.rs:3:7"]), [("a.rs".to_string(), Some(at(2, 6)))]);
+        assert_eq!(files(&["a:b.rs"]), [("a:b.rs".to_string(), None)]);
+        assert_eq!(files(&[":3"]), [(":3".to_string(), None)]);
+    }
+    #[test]
+    fn prefers_existing_files_named_like_locations() {
+        let file = std::env::temp_dir().join(format!("hecto-args-{}:3", std::process::id()));
+        std::fs::write(&file, "").unwrap();
+        let name = file.to_string_lossy().to_string();
+        assert_eq!(files(&[&name]), [(name.clone(), None)]);
+        std::fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn reads_options() {
+        assert!(matches!(parse_args(&["-R", "a"]), Ok(Command::Edit(Args { readonly: true, .. }))));
+        assert!(matches!(parse_args(&["a", "--readonly"]), Ok(Command::Edit(Args { readonly: true, .. }))));
+        assert!(matches!(parse_args(&["a", "--help", "--bogus"]), Ok(Command::Help)));
+        assert!(matches!(parse_args(&["-V"]), Ok(Command::Version)));
+        assert_eq!(files(&["-", "--", "-R", "+3"]), [("-".to_string(), None), ("-R".to_string(), None), ("+3".to_string(), None)]);
+    }
+    #[test]
+    fn rejects_arguments_that_make_no_sense() {
+        assert_eq!(parse_args(&["--bogus"]).err(), Some("unknown option --bogus".to_string()));
+        assert_eq!(parse_args(&["+x", "a"]).err(), Some("invalid line number +x".to_string()));
+        assert_eq!(parse_args(&["a", "+3"]).err(), Some("+LINE has to come before a file".to_string()));
+    }
+}
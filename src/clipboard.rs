@@ -0,0 +1,134 @@
+use crate::Config;
+use crate::Terminal;
+use std::io::{Error, ErrorKind, Read, Write};
+use std::process::{Command, Stdio};
+use std::sync::mpsc;
+use std::thread;
+use std::time::{Duration, Instant};
+//how long copying and pasting wait for a clipboard helper before giving up on it,
+//pasting then uses the internal register
+const HELPER_TIMEOUT: Duration = Duration::from_millis(500);
+//register holding the last copied or cut text. linewise entries come from
+//copying a whole line without a selection and are pasted above the cursor line.
+//copies are also handed to the system clipboard when that is configured
+#[derive(Default)]
+pub struct Clipboard {
+    text: String,
+    linewise: bool,
+    osc52: bool,
+    copy_command: Option<String>,
+    paste_command: Option<String>,
+}
+impl Clipboard {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            osc52: config.osc52,
+            copy_command: config.copy_command.clone(),
+            paste_command: config.paste_command.clone(),
+            ..Self::default()
+        }
+    }
+    pub fn set(&mut self, text: String, linewise: bool) {
+        if self.osc52 {
+            Terminal::set_clipboard(&text);
+        }
+        if let Some(command) = &self.copy_command {
+            //a missing or failing helper leaves the internal register as the only copy
+            let _ = run_copy(command, &text);
+        }
+        self.text = text;
+        self.linewise = linewise;
+    }
+    //the text to paste and whether it is linewise. the system clipboard wins
+    //when it holds something other than what was last copied in here
+    pub fn contents(&self) -> (String, bool) {
+        let system = self.paste_command.as_deref().and_then(run_paste);
+        match system {
+            Some(text) if !text.is_empty() && text != self.text => (text, false),
+            _ => (self.text.clone(), self.linewise),
+        }
+    }
+}
+fn command(command_line: &str) -> Option<Command> {
+    let mut parts = command_line.split_whitespace();
+    let mut command = Command::new(parts.next()?);
+    command.args(parts).stderr(Stdio::null());
+    Some(command)
+}
+//the text is written on a thread of its own, so a helper that neither reads
+//it nor exits is killed once HELPER_TIMEOUT runs out
+fn run_copy(command_line: &str, text: &str) -> std::io::Result<()> {
+    let Some(mut command) = command(command_line) else {
+        return Ok(());
+    };
+    let mut child = command.stdin(Stdio::piped()).stdout(Stdio::null()).spawn()?;
+    if let Some(mut stdin) = child.stdin.take() {
+        let text = text.to_string();
+        thread::spawn(move || stdin.write_all(text.as_bytes()));
+    }
+    let start = Instant::now();
+    while child.try_wait()?.is_none() {
+        if start.elapsed() > HELPER_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return Err(Error::new(ErrorKind::TimedOut, "the copy helper did not finish"));
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    Ok(())
+}
+//the output is read on a thread of its own, so a helper that hangs is killed
+//once HELPER_TIMEOUT runs out instead of freezing the editor
+fn run_paste(command_line: &str) -> Option<String> {
+    let mut child = command(command_line)?
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut stdout = child.stdout.take()?;
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        let mut bytes = Vec::new();
+        let read = stdout.read_to_end(&mut bytes).map(|_| bytes);
+        let _ = sender.send(read);
+    });
+    let Ok(Ok(bytes)) = receiver.recv_timeout(HELPER_TIMEOUT) else {
+        let _ = child.kill();
+        let _ = child.wait();
+        return None;
+    };
+    if !child.wait().ok()?.success() {
+        return None;
+    }
+    String::from_utf8(bytes).ok()
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn pastes_helper_output() {
+        assert_eq!(run_paste("echo pasted"), Some("pasted\n".to_string()));
+    }
+    #[test]
+    fn gives_up_on_a_hung_helper() {
+        let start = Instant::now();
+        assert_eq!(run_paste("sleep 10"), None);
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+    #[test]
+    fn copies_to_the_helper() {
+        assert!(run_copy("cat", "copied").is_ok());
+    }
+    #[test]
+    fn gives_up_on_a_hung_copy_helper() {
+        let start = Instant::now();
+        assert!(run_copy("sleep 10", "copied").is_err());
+        assert!(start.elapsed() < Duration::from_secs(5));
+    }
+    #[test]
+    fn falls_back_to_the_register() {
+        let mut clipboard = Clipboard { paste_command: Some("sleep 10".to_string()), ..Clipboard::default() };
+        clipboard.set("copied".to_string(), true);
+        assert_eq!(clipboard.contents(), ("copied".to_string(), true));
+    }
+}
@@ -0,0 +1,74 @@
+use std::env;
+use std::path::Path;
+//editor options, read from HECTO_* environment variables
+pub struct Config {
+    pub tab_width: usize,
+    pub expand_tabs: bool,
+    //write copied text to the terminal clipboard with OSC 52
+    pub osc52: bool,
+    //local programs reading the copied text from stdin and
+    //printing the clipboard to stdout, like xclip or wl-copy
+    pub copy_command: Option<String>,
+    pub paste_command: Option<String>,
+    //keep the previous contents of a saved file in `file~`
+    pub backup: bool,
+}
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            expand_tabs: false,
+            osc52: true,
+            copy_command: None,
+            paste_command: None,
+            backup: false,
+        }
+    }
+}
+impl Config {
+    pub fn from_env() -> Self {
+        let mut config = Self::default();
+        if let Some(tab_width) = env::var("HECTO_TAB_WIDTH").ok().and_then(|value| value.parse().ok()) {
+            if tab_width > 0 {
+                config.tab_width = tab_width;
+            }
+        }
+        if let Ok(value) = env::var("HECTO_EXPAND_TABS") {
+            config.expand_tabs = is_enabled(&value);
+        }
+        if let Ok(value) = env::var("HECTO_OSC52") {
+            config.osc52 = is_enabled(&value);
+        }
+        if let Ok(value) = env::var("HECTO_BACKUP") {
+            config.backup = is_enabled(&value);
+        }
+        let (copy_command, paste_command) = default_clipboard_commands();
+        config.copy_command = env::var("HECTO_COPY_COMMAND").ok().or(copy_command);
+        config.paste_command = env::var("HECTO_PASTE_COMMAND").ok().or(paste_command);
+        config
+    }
+}
+fn is_enabled(value: &str) -> bool {
+    matches!(value, "1" | "true" | "yes")
+}
+//the first clipboard helper pair for the running session that is installed
+fn default_clipboard_commands() -> (Option<String>, Option<String>) {
+    let mut candidates = Vec::new();
+    if env::var_os("WAYLAND_DISPLAY").is_some() {
+        candidates.push(("wl-copy", "wl-paste --no-newline"));
+    }
+    if env::var_os("DISPLAY").is_some() {
+        candidates.push(("xclip -selection clipboard", "xclip -selection clipboard -o"));
+        candidates.push(("xsel --clipboard --input", "xsel --clipboard --output"));
+    }
+    if cfg!(target_os = "macos") {
+        candidates.push(("pbcopy", "pbpaste"));
+    }
+    candidates
+        .into_iter()
+        .find(|(copy, _)| copy.split_whitespace().next().is_some_and(is_in_path))
+        .map_or((None, None), |(copy, paste)| (Some(copy.to_string()), Some(paste.to_string())))
+}
+fn is_in_path(program: &str) -> bool {
+    env::var_os("PATH").is_some_and(|paths| env::split_paths(&paths).any(|dir| Path::new(&dir).join(program).is_file()))
+}
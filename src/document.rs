@@ -1,80 +1,1025 @@
 use std::fs;
+use std::cmp;
 use crate::Row;
 use crate::Position;
-use std::io::{Error, Write};
-#[derive(Default)]
+use crate::SearchDirection;
+use crate::history::{Edit, History};
+use crate::FileType;
+use crate::Encoding;
+use ropey::{Rope, RopeBuilder};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs::Permissions;
+use std::hash::{DefaultHasher, Hasher};
+use std::io::{self, BufRead, BufReader, BufWriter, Error, ErrorKind, IntoInnerError, Read, Write};
+use std::path::{Path, PathBuf};
+use std::process::{self, Stdio};
+use std::rc::Rc;
+use std::sync::OnceLock;
+use std::sync::mpsc::{self, Receiver, TryRecvError};
+use std::thread::{self, JoinHandle};
+use std::time::SystemTime;
+use unicode_segmentation::UnicodeSegmentation;
+//bytes read from a file at a time, the first chunk is all that is needed to show it
+const CHUNK_SIZE: usize = 1 << 20;
+//rows kept measured at most, enough for any screen. a search through a long file
+//fills it quickly, so it is simply emptied when full
+const ROW_CACHE_SIZE: usize = 1 << 12;
+//rows read from a file, the last chunk comes with what is known about the whole file
+type Chunk = (Rope, Option<FileEnd>);
+#[derive(Clone, Copy)]
+struct FileEnd {
+    final_newline: bool,
+    hash: u64,
+}
+//what a swap file found next to an opened file holds
+pub enum Swap {
+    //unsaved changes of a session that ended without saving them
+    Stale(String),
+    //the journal of a session still editing the file, named by where it runs
+    Live(String),
+}
+//the file as last read or written, to notice when something else changes it
+struct DiskState {
+    modified: Option<SystemTime>,
+    len: u64,
+    hash: u64,
+}
+//passes reads through while hashing every byte
+struct HashingReader<R> {
+    inner: R,
+    hasher: DefaultHasher,
+}
+impl<R: Read> Read for HashingReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> Result<usize, Error> {
+        let read = self.inner.read(buf)?;
+        self.hasher.write(&buf[..read]);
+        Ok(read)
+    }
+}
+//line break written after each row on save
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LineEnding {
+    #[default]
+    Lf,
+    Crlf,
+}
+impl LineEnding {
+    //the style of the first line break in `bytes`
+    fn detect(bytes: &[u8]) -> Self {
+        match bytes.iter().position(|&byte| byte == b'\n') {
+            Some(index) if index > 0 && bytes[index - 1] == b'\r' => Self::Crlf,
+            _ => Self::Lf,
+        }
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Lf => "LF",
+            Self::Crlf => "CRLF",
+        }
+    }
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Self::Lf => "\n",
+            Self::Crlf => "\r\n",
+        }
+    }
+}
+#[allow(clippy::struct_excessive_bools)]
 pub struct Document {
-    rows: Vec<Row>,
+    //every row followed by a line break, the way it is saved
+    text: Rope,
+    //rows with their grapheme boundaries and widths worked out, by index.
+    //dropped by edits to them or to the rows before them
+    rows: RefCell<HashMap<usize, Rc<Row>>>,
     pub file: Option<String>,
+    history: History,
+    dirty: bool,
+    file_type: FileType,
+    //whether each row leaves a multiline comment open, known up to
+    //the last row highlighted and dropped from the first edited row on
+    comments: Vec<bool>,
+    //chunks of the file still being read in the background
+    loading: Option<Receiver<Result<Chunk, Error>>>,
+    encoding: Encoding,
+    //whether the file starts with a byte order mark
+    bom: bool,
+    line_ending: LineEnding,
+    //whether the last row is followed by a line break as well
+    final_newline: bool,
+    //whether the swap file holds the latest changes
+    swapped: bool,
+    //the swap file being written in the background
+    swap_write: Option<JoinHandle<Result<(), Error>>>,
+    //whether another running session journals the file, its swap file is left alone then
+    swap_taken: bool,
+    //whether this session wrote the swap file, one left by another is only removed on request
+    swap_owned: bool,
+    disk: Option<DiskState>,
+    //whether the file was noticed to be changed by something else
+    changed_on_disk: bool,
+    //whether edits and saving are refused
+    readonly: bool,
+}
+impl Default for Document {
+    fn default() -> Self {
+        Self {
+            text: Rope::new(),
+            rows: RefCell::default(),
+            file: None,
+            history: History::default(),
+            dirty: false,
+            file_type: FileType::default(),
+            comments: Vec::new(),
+            loading: None,
+            encoding: Encoding::default(),
+            bom: false,
+            line_ending: LineEnding::default(),
+            final_newline: true,
+            swapped: false,
+            swap_write: None,
+            swap_taken: false,
+            swap_owned: false,
+            disk: None,
+            changed_on_disk: false,
+            readonly: false,
+        }
+    }
 }
 impl Document {
+    //reads the first chunk of `file` right away and leaves the rest to a
+    //background thread, whose chunks are taken in by `load`
     pub fn open(file: &str) -> std::io::Result<Self> {
-        let contents = fs::read_to_string(file)?;
-        let mut rows = Vec::new();
-        for line in contents.lines() {
-            rows.push(Row::from(line));
-        }
+        let handle = fs::File::open(file)?;
+        let metadata = handle.metadata()?;
+        let disk = DiskState {
+            modified: metadata.modified().ok(),
+            len: metadata.len(),
+            hash: 0,
+        };
         Ok(Self {
-            rows,
             file: Some(file.to_string()),
+            file_type: FileType::from(file),
+            ..Self::read(handle, Some(disk))?
+        })
+    }
+    //an unnamed document holding what `reader` gives, read the same way as a file
+    pub fn from_reader<R: Read + Send + 'static>(reader: R) -> std::io::Result<Self> {
+        Self::read(reader, None)
+    }
+    fn read<R: Read + Send + 'static>(reader: R, mut disk: Option<DiskState>) -> std::io::Result<Self> {
+        let mut reader = BufReader::new(HashingReader { inner: reader, hasher: DefaultHasher::new() });
+        let sample = reader.fill_buf()?;
+        let (encoding, bom) = Encoding::detect(sample);
+        let bom_len = if bom { encoding.bom().len() } else { 0 };
+        let line_ending = LineEnding::detect(encoding.decode(&sample[bom_len..]).as_bytes());
+        reader.consume(bom_len);
+        let (text, end) = read_chunk(&mut reader, encoding, line_ending)?;
+        let loading = if end.is_some() {
+            None
+        } else {
+            let (sender, receiver) = mpsc::channel();
+            thread::spawn(move || loop {
+                let chunk = read_chunk(&mut reader, encoding, line_ending);
+                let done = !matches!(chunk, Ok((_, None)));
+                if sender.send(chunk).is_err() || done {
+                    break;
+                }
+            });
+            Some(receiver)
+        };
+        if let (Some(disk), Some(end)) = (&mut disk, end) {
+            disk.hash = end.hash;
+        }
+        Ok(Self {
+            text,
+            loading,
+            encoding,
+            bom,
+            line_ending,
+            final_newline: end.is_none_or(|end| end.final_newline),
+            disk,
+            ..Self::default()
         })
     }
-    pub fn row(&self, index: usize) -> Option<&Row> {
-        self.rows.get(index)
+    //appends the chunks read since the last call, returns whether there were any.
+    //if reading fails the document forgets its file, so what was loaded
+    //cannot be saved over it by accident
+    pub fn load(&mut self) -> Result<bool, Error> {
+        self.receive(false)
+    }
+    //waits for the rest of the file
+    pub fn finish_loading(&mut self) -> Result<(), Error> {
+        self.receive(true).map(|_| ())
+    }
+    pub fn is_loading(&self) -> bool {
+        self.loading.is_some()
+    }
+    fn receive(&mut self, wait: bool) -> Result<bool, Error> {
+        let mut changed = false;
+        while let Some(receiver) = &self.loading {
+            let chunk = if wait {
+                receiver.recv().map_err(|_| TryRecvError::Disconnected)
+            } else {
+                receiver.try_recv()
+            };
+            match chunk {
+                Ok(Ok((text, end))) => {
+                    self.text.append(text);
+                    if let Some(end) = end {
+                        self.final_newline = end.final_newline;
+                        if let Some(disk) = &mut self.disk {
+                            disk.hash = end.hash;
+                        }
+                    }
+                    changed = true;
+                },
+                Ok(Err(error)) => {
+                    self.loading = None;
+                    self.file = None;
+                    return Err(error);
+                },
+                Err(TryRecvError::Empty) => break,
+                Err(TryRecvError::Disconnected) => self.loading = None,
+            }
+        }
+        Ok(changed)
+    }
+    pub fn row(&self, index: usize) -> Option<Rc<Row>> {
+        if index >= self.len() {
+            return None;
+        }
+        let mut rows = self.rows.borrow_mut();
+        if let Some(row) = rows.get(&index) {
+            return Some(Rc::clone(row));
+        }
+        if rows.len() >= ROW_CACHE_SIZE {
+            rows.clear();
+        }
+        let line = self.text.line(index);
+        let line = line.slice(..line.len_chars() - 1);
+        let row = Rc::new(match line.as_str() {
+            Some(slice) => Row::from(slice),
+            None => Row::from(&line.to_string()[..]),
+        });
+        rows.insert(index, Rc::clone(&row));
+        Some(row)
+    }
+    //row `index` with highlighting for drawing, matches of `word` included
+    pub fn highlighted_row(&self, index: usize, word: Option<&str>) -> Option<Row> {
+        let mut row = Row::clone(&*self.row(index)?);
+        let start_with_comment = index > 0 && self.comments.get(index - 1).copied().unwrap_or(false);
+        row.highlight(self.file_type.highlighting_options(), word, start_with_comment);
+        Some(row)
     }
     pub fn is_empty(&self) -> bool {
-        self.rows.is_empty()
+        self.text.len_chars() == 0
     }
     pub fn len(&self) -> usize {
-        self.rows.len()
+        self.text.len_lines() - 1
     }
-    pub fn insert(&mut self, pos: &Position, c: char) {
-        if c == '\n' {
-            self.insert_line_break(pos);
+    pub fn file_type(&self) -> String {
+        self.file_type.name()
+    }
+    //works out the multiline comment state of rows up to `until`, carrying it from row to row.
+    //rows highlighted since the last edit are not looked at again
+    pub fn highlight(&mut self, until: usize) {
+        let opts = self.file_type.highlighting_options();
+        if !opts.multiline_comments() {
             return;
         }
-        if pos.y == self.len() {
-            let mut row = Row::default();
-            row.insert(0, c);
-            self.rows.push(row);
-        } else if pos.y < self.len() {
-            let row = self.rows.get_mut(pos.y).unwrap();
-            row.insert(pos.x, c);
+        let until = cmp::min(until, self.len());
+        while self.comments.len() < until {
+            let index = self.comments.len();
+            let start_with_comment = self.comments.last().copied().unwrap_or(false);
+            let in_comment = self.row(index).map_or(start_with_comment, |row| Row::clone(&row).highlight(opts, None, start_with_comment));
+            self.comments.push(in_comment);
         }
     }
-    pub fn insert_line_break(&mut self, pos: &Position) {
+    pub fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+    pub fn has_bom(&self) -> bool {
+        self.bom
+    }
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+    pub fn has_final_newline(&self) -> bool {
+        self.final_newline
+    }
+    //these only change what is written on save, so they are not undoable edits
+    pub fn set_encoding(&mut self, encoding: Encoding, bom: bool) {
+        if self.encoding != encoding || self.bom != bom {
+            self.encoding = encoding;
+            self.bom = bom;
+            self.mark_dirty();
+        }
+    }
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if self.line_ending != line_ending {
+            self.line_ending = line_ending;
+            self.mark_dirty();
+        }
+    }
+    pub fn set_final_newline(&mut self, final_newline: bool) {
+        if self.final_newline != final_newline {
+            self.final_newline = final_newline;
+            self.mark_dirty();
+        }
+    }
+    fn mark_dirty(&mut self) {
+        self.dirty = true;
+        self.swapped = false;
+    }
+    //true when there are edits that have not been saved yet
+    pub fn is_dirty(&self) -> bool {
+        self.dirty
+    }
+    pub fn is_readonly(&self) -> bool {
+        self.readonly
+    }
+    pub fn set_readonly(&mut self, readonly: bool) {
+        self.readonly = readonly;
+    }
+    pub fn find(&self, query: &str, at: &Position, direction: SearchDirection) -> Option<Position> {
+        if at.y >= self.len() {
+            return None;
+        }
+        let mut position = *at;
+        let (start, end) = match direction {
+            SearchDirection::Forward => (at.y, self.len()),
+            SearchDirection::Backward => (0, at.y.saturating_add(1)),
+        };
+        for _ in start..end {
+            let row = self.row(position.y)?;
+            if let Some(x) = row.find(query, position.x, direction) {
+                position.x = x;
+                return Some(position);
+            }
+            match direction {
+                SearchDirection::Forward => {
+                    position.y = position.y.saturating_add(1);
+                    position.x = 0;
+                },
+                SearchDirection::Backward => {
+                    position.y = position.y.saturating_sub(1);
+                    position.x = self.row(position.y).map_or(0, |row| row.len());
+                },
+            }
+        }
+        None
+    }
+    pub fn insert(&mut self, pos: &Position, c: char) {
+        self.load_past(pos.y);
         if pos.y > self.len() {
             return;
         }
+        self.mark_dirty();
         if pos.y == self.len() {
-            self.rows.push(Row::default());
-            return;
+            self.history.record(Edit::AppendRow);
+            self.append_row();
+            if c == '\n' {
+                return;
+            }
         }
-        let new_row = self.rows.get_mut(pos.y).unwrap().split(pos.x);
-        self.rows.insert(pos.y + 1, new_row);
+        self.history.record(Edit::Insert { at: *pos, text: c.to_string() });
+        self.insert_text(pos, &c.to_string());
+    }
+    //replaces the text between `start` and `end` as a single edit,
+    //returns the position right after the inserted text
+    pub fn replace(&mut self, start: &Position, end: &Position, text: &str) -> Position {
+        self.delete_range(start, end);
+        self.insert_str(start, text)
+    }
+    //inserts text spanning any number of rows as a single edit that is undone on its
+    //own, apart from any typing around it. returns the position right after it
+    pub fn insert_str(&mut self, at: &Position, text: &str) -> Position {
+        self.load_past(at.y);
+        if at.y > self.len() || text.is_empty() {
+            return *at;
+        }
+        self.mark_dirty();
+        self.history.keep_apart();
+        if at.y == self.len() {
+            self.history.record(Edit::AppendRow);
+            self.append_row();
+        }
+        self.history.record(Edit::Insert { at: *at, text: text.to_string() });
+        self.insert_text(at, text)
+    }
+    //removes the text between `start` and `end` as a single edit and returns it
+    pub fn delete_range(&mut self, start: &Position, end: &Position) -> String {
+        if start >= end || end.y >= self.len() {
+            return String::new();
+        }
+        self.mark_dirty();
+        let removed = self.delete_text(start, end);
+        self.history.record(Edit::Delete { at: *start, text: removed.clone() });
+        removed
+    }
+    //the text between `start` and `end`, rows joined by line breaks
+    pub fn text_range(&self, start: &Position, end: &Position) -> String {
+        if start >= end || end.y >= self.len() {
+            return String::new();
+        }
+        self.text.slice(self.char_index(start)..self.char_index(end)).to_string()
+    }
+    //the whole document with rows joined by line breaks
+    pub fn text(&self) -> String {
+        if self.is_empty() {
+            return String::new();
+        }
+        self.text.slice(..self.text.len_chars() - 1).to_string()
+    }
+    //converts between positions and byte offsets into `text`
+    pub fn offset_of(&self, pos: &Position) -> usize {
+        if pos.y >= self.len() {
+            return self.text.len_bytes();
+        }
+        self.text.line_to_byte(pos.y) + self.row(pos.y).map_or(0, |row| row.byte_index(pos.x))
+    }
+    pub fn position_at(&self, offset: usize) -> Position {
+        if self.is_empty() {
+            return Position::default();
+        }
+        let offset = cmp::min(offset, self.text.len_bytes() - 1);
+        let y = self.text.byte_to_line(offset);
+        let x = self.row(y).map_or(0, |row| row.grapheme_index(offset - self.text.line_to_byte(y)));
+        Position { x, y }
     }
     pub fn delete(&mut self, pos: &Position) {
+        if let Some(text) = self.delete_grapheme(pos) {
+            self.history.record(Edit::Delete { at: *pos, text });
+            self.mark_dirty();
+        }
+    }
+    //closes the edits made since the last checkpoint into one undo step
+    pub fn checkpoint(&mut self, cursor_before: &Position, cursor_after: &Position) {
+        self.history.commit(*cursor_before, *cursor_after);
+    }
+    pub fn undo(&mut self) -> Option<Position> {
+        let step = self.history.pop_undo()?;
+        for edit in step.edits.iter().rev() {
+            match edit {
+                Edit::Insert { at, text } => {
+                    let end = Self::end_of(at, text);
+                    self.delete_text(at, &end);
+                },
+                Edit::Delete { at, text } => {
+                    self.insert_text(at, text);
+                },
+                Edit::AppendRow => {
+                    self.pop_row();
+                },
+            }
+        }
+        self.mark_dirty();
+        let cursor = step.cursor_before;
+        self.history.push_redo(step);
+        Some(cursor)
+    }
+    pub fn redo(&mut self) -> Option<Position> {
+        let step = self.history.pop_redo()?;
+        for edit in &step.edits {
+            match edit {
+                Edit::Insert { at, text } => {
+                    self.insert_text(at, text);
+                },
+                Edit::Delete { at, text } => {
+                    let end = Self::end_of(at, text);
+                    self.delete_text(at, &end);
+                },
+                Edit::AppendRow => self.append_row(),
+            }
+        }
+        self.mark_dirty();
+        let cursor = step.cursor_after;
+        self.history.push_undo(step);
+        Some(cursor)
+    }
+    //position right after `text` once it is inserted at `at`
+    fn end_of(at: &Position, text: &str) -> Position {
+        match text.rsplit_once('\n') {
+            Some((before, last_line)) => Position {
+                x: last_line.graphemes(true).count(),
+                y: at.y + before.matches('\n').count() + 1,
+            },
+            None => Position { x: at.x + text.graphemes(true).count(), y: at.y },
+        }
+    }
+    //rows cannot be appended while the rest of the file is still coming,
+    //so writing past the loaded rows waits for it
+    fn load_past(&mut self, y: usize) {
+        if y >= self.len() {
+            //a failure leaves the document without its file, see `load`
+            let _ = self.finish_loading();
+        }
+    }
+    //index into `text` of the char the grapheme at `pos` starts with
+    fn char_index(&self, pos: &Position) -> usize {
+        if pos.y >= self.len() {
+            return self.text.len_chars();
+        }
+        let row = self.row(pos.y).unwrap_or_default();
+        self.text.line_to_char(pos.y) + row.as_str()[..row.byte_index(pos.x)].chars().count()
+    }
+    fn append_row(&mut self) {
+        self.text.insert_char(self.text.len_chars(), '\n');
+    }
+    fn pop_row(&mut self) {
+        let len = self.text.len_chars();
+        self.comments.truncate(self.len().saturating_sub(1));
+        self.text.remove(len.saturating_sub(1)..len);
+        self.forget_rows(self.len(), true);
+    }
+    fn insert_text(&mut self, pos: &Position, text: &str) -> Position {
+        if pos.y >= self.len() {
+            return *pos;
+        }
+        self.comments.truncate(pos.y);
+        self.text.insert(self.char_index(pos), text);
+        self.forget_rows(pos.y, text.contains('\n'));
+        Self::end_of(pos, text)
+    }
+    //removes everything between `start` and `end` and returns it
+    fn delete_text(&mut self, start: &Position, end: &Position) -> String {
+        self.comments.truncate(start.y);
+        let range = self.char_index(start)..self.char_index(end);
+        let removed = self.text.slice(range.clone()).to_string();
+        self.text.remove(range);
+        self.forget_rows(start.y, start.y != end.y);
+        removed
+    }
+    //drops the measured row `y`, and with `shifted` the ones after it, which
+    //moved to other indices
+    fn forget_rows(&self, y: usize, shifted: bool) {
+        let mut rows = self.rows.borrow_mut();
+        if shifted {
+            rows.retain(|&index, _| index < y);
+        } else {
+            rows.remove(&y);
+        }
+    }
+    fn delete_grapheme(&mut self, pos: &Position) -> Option<String> {
         let len = self.len();
         if pos.y >= len {
-            return;
+            return None;
         }
-        if pos.x == self.rows.get_mut(pos.y).unwrap().len() && pos.y < len - 1 {
-            let next_row = self.rows.remove(pos.y + 1);
-            let row = self.rows.get_mut(pos.y).unwrap();
-            row.append(next_row);
+        let row_len = self.row(pos.y).map_or(0, |row| row.len());
+        let next = if pos.x < row_len {
+            Position { x: pos.x + 1, y: pos.y }
+        } else if pos.y < len - 1 {
+            Position { x: 0, y: pos.y + 1 }
         } else {
-            let row = self.rows.get_mut(pos.y).unwrap();
-            row.delete(pos.x);
+            return None;
+        };
+        Some(self.delete_text(pos, &next))
+    }
+    //writes to a temporary file next to the target, flushed to disk and then renamed
+    //over it, so a failed save leaves the old file as it was. with `backup` the old
+    //contents are copied to `file~` first
+    pub fn save(&mut self, backup: bool) -> Result<(), Error> {
+        self.finish_loading()?;
+        let Some(file_name) = self.file.clone() else {
+            return Ok(());
+        };
+        //checked before anything is written
+        if !self.text.chunks().all(|chunk| self.encoding.can_encode(chunk)) {
+            return Err(Error::new(
+                ErrorKind::InvalidData,
+                format!("the text cannot be written as {}", self.encoding.name()),
+            ));
+        }
+        //a symbolic link keeps pointing to the saved file
+        let path = fs::canonicalize(&file_name).unwrap_or_else(|_| PathBuf::from(&file_name));
+        let permissions = match fs::metadata(&path) {
+            Ok(metadata) if metadata.permissions().readonly() => {
+                return Err(Error::new(ErrorKind::PermissionDenied, "the file is read-only"));
+            },
+            Ok(metadata) => Some(metadata.permissions()),
+            Err(_) => None,
+        };
+        let temp = path.with_file_name(format!(
+            ".{}.hecto-{}",
+            path.file_name().unwrap_or_default().to_string_lossy(),
+            process::id()
+        ));
+        let result = self
+            .write_to(&temp, permissions.clone())
+            .and_then(|hash| {
+                if backup && permissions.is_some() {
+                    let mut backup = path.clone().into_os_string();
+                    backup.push("~");
+                    fs::copy(&path, &backup).map_err(|error| with_context(&error, "could not write the backup"))?;
+                }
+                fs::rename(&temp, &path).map_err(|error| with_context(&error, "could not replace the file"))?;
+                let metadata = fs::metadata(&path)?;
+                Ok(DiskState { modified: metadata.modified().ok(), len: metadata.len(), hash })
+            });
+        if result.is_err() {
+            let _ = fs::remove_file(&temp);
         }
+        self.disk = Some(result?);
+        self.changed_on_disk = false;
+        //makes the rename itself durable, not every system allows syncing a directory
+        let dir = path.parent().filter(|dir| !dir.as_os_str().is_empty()).unwrap_or(Path::new("."));
+        if let Ok(dir) = fs::File::open(dir) {
+            let _ = dir.sync_all();
+        }
+        self.dirty = false;
+        self.file_type = FileType::from(&file_name);
+        self.remove_swap();
+        Ok(())
     }
-    pub fn save(&self) -> Result<(), Error> {
-        if let Some(file) = &self.file {
-            let mut file = fs::File::create(file)?;
-            for row in &self.rows {
-                file.write_all(row.as_bytes())?;
-                file.write_all(b"\n")?;
-            }
+    //where unsaved changes are kept in case the editor dies, next to the file
+    pub fn swap_file(&self) -> Option<PathBuf> {
+        let path = Path::new(self.file.as_ref()?);
+        let name = path.file_name()?.to_string_lossy();
+        Some(path.with_file_name(format!(".{name}.hecto.swp")))
+    }
+    //what the swap file next to the file holds, its text has rows joined by line breaks.
+    //a swap file of a session that is still running is left to that session
+    pub fn read_swap(&mut self) -> Result<Option<Swap>, Error> {
+        let Some(swap_file) = self.swap_file().filter(|swap_file| swap_file.exists()) else {
+            return Ok(None);
+        };
+        let contents = fs::read_to_string(swap_file)?;
+        let (owner, mut text) = match contents.split_once('\n') {
+            Some((header, text)) if header.starts_with(SWAP_HEADER) => (Some(header[SWAP_HEADER.len()..].to_string()), text.to_string()),
+            _ => (None, contents),
+        };
+        text.pop();
+        if let Some(owner) = owner.filter(|owner| is_running(owner)) {
+            self.swap_taken = true;
+            return Ok(Some(Swap::Live(owner)));
+        }
+        Ok(Some(Swap::Stale(text)))
+    }
+    //starts writing the text to the swap file in the background if it changed since the
+    //last time, after the previous write is done. returns the error of a previous write.
+    //escaped bytes go in as they are stored, so they come back the same
+    pub fn write_swap(&mut self) -> Result<(), Error> {
+        if self.swap_write.as_ref().is_some_and(|write| !write.is_finished()) {
+            return Ok(());
+        }
+        if let Some(write) = self.swap_write.take() {
+            write.join().unwrap_or_else(|_| Err(Error::other("the swap file writer panicked")))?;
+        }
+        if !self.dirty || self.swapped || self.swap_taken || self.is_loading() {
+            return Ok(());
         }
+        let Some(swap_file) = self.swap_file() else {
+            return Ok(());
+        };
+        self.swapped = true;
+        self.swap_owned = true;
+        //clones of a rope share their nodes, so taking one is cheap
+        let text = self.text.clone();
+        self.swap_write = Some(thread::spawn(move || write_swap_file(&swap_file, &text)));
         Ok(())
     }
-}
\ No newline at end of file
+    //drops the swap file this session wrote once its changes are saved or thrown away
+    pub fn remove_swap(&mut self) {
+        if let Some(write) = self.swap_write.take() {
+            let _ = write.join();
+        }
+        if self.swap_owned {
+            self.discard_swap();
+        }
+        self.swapped = false;
+        self.swap_owned = false;
+    }
+    //drops a swap file left by an earlier session, unless another running one writes it
+    pub fn discard_swap(&mut self) {
+        if let Some(swap_file) = self.swap_file().filter(|_| !self.swap_taken) {
+            let _ = fs::remove_file(swap_file);
+        }
+    }
+    //returns the hash of what was written
+    fn write_to(&self, path: &Path, permissions: Option<Permissions>) -> Result<u64, Error> {
+        let file = fs::File::create(path).map_err(|error| with_context(&error, "could not create a temporary file"))?;
+        if let Some(permissions) = permissions {
+            file.set_permissions(permissions)?;
+        }
+        let mut writer = BufWriter::new(file);
+        let mut hasher = DefaultHasher::new();
+        if self.bom {
+            writer.write_all(self.encoding.bom())?;
+            hasher.write(self.encoding.bom());
+        }
+        let end = if self.final_newline {
+            self.text.len_chars()
+        } else {
+            self.text.len_chars().saturating_sub(1)
+        };
+        for chunk in self.text.slice(..end).chunks() {
+            let bytes = self.encoding.encode(&chunk.replace('\n', self.line_ending.as_str()));
+            writer.write_all(&bytes)?;
+            hasher.write(&bytes);
+        }
+        let file = writer.into_inner().map_err(IntoInnerError::into_error)?;
+        file.sync_all()?;
+        Ok(hasher.finish())
+    }
+    //looks at the file on disk, returns true when it is first noticed that something
+    //else changed it since it was opened or saved. a new modification time alone,
+    //as left by touch, does not count while the contents stay the same
+    pub fn check_disk(&mut self) -> bool {
+        if self.changed_on_disk || self.is_loading() {
+            return false;
+        }
+        let (Some(file), Some(disk)) = (&self.file, &mut self.disk) else {
+            return false;
+        };
+        let Ok(metadata) = fs::metadata(file) else {
+            return false;
+        };
+        if metadata.modified().ok() == disk.modified && metadata.len() == disk.len {
+            return false;
+        }
+        if metadata.len() == disk.len && hash_file(file).is_ok_and(|hash| hash == disk.hash) {
+            disk.modified = metadata.modified().ok();
+            return false;
+        }
+        self.changed_on_disk = true;
+        true
+    }
+    pub fn is_changed_on_disk(&self) -> bool {
+        self.changed_on_disk
+    }
+    //for a document saved under a new name
+    pub fn forget_disk(&mut self) {
+        self.disk = None;
+        self.changed_on_disk = false;
+    }
+}
+//swap files start with a line naming the session writing them
+const SWAP_HEADER: &str = "hecto swap file of ";
+//writes through a temporary file that is synced and renamed over the swap
+//file, so a crash never leaves a swap file cut short
+fn write_swap_file(swap_file: &Path, text: &Rope) -> Result<(), Error> {
+    let temp = swap_file.with_file_name(format!(
+        "{}-{}",
+        swap_file.file_name().unwrap_or_default().to_string_lossy(),
+        process::id()
+    ));
+    let result = fs::File::create(&temp).and_then(|file| {
+        let mut writer = BufWriter::new(file);
+        writeln!(writer, "{SWAP_HEADER}{}", owner())?;
+        for chunk in text.chunks() {
+            writer.write_all(chunk.as_bytes())?;
+        }
+        writer.into_inner().map_err(IntoInnerError::into_error)?.sync_all()?;
+        fs::rename(&temp, swap_file)
+    });
+    if result.is_err() {
+        let _ = fs::remove_file(&temp);
+    }
+    result
+}
+//this session as named in swap files, by process id and host
+fn owner() -> String {
+    static HOST: OnceLock<String> = OnceLock::new();
+    let host = HOST.get_or_init(|| {
+        fs::read_to_string("/proc/sys/kernel/hostname")
+            .ok()
+            .or_else(|| {
+                let output = process::Command::new("hostname").stderr(Stdio::null()).output().ok()?;
+                String::from_utf8(output.stdout).ok()
+            })
+            .map(|host| host.trim().to_string())
+            .unwrap_or_default()
+    });
+    format!("process {} on {host}", process::id())
+}
+//whether the session named `owner` still runs. one on another host cannot be
+//looked at, so it counts as running. where there is neither /proc nor kill,
+//every swap file counts as left behind
+fn is_running(owner: &str) -> bool {
+    let Some((pid, host)) = owner.strip_prefix("process ").and_then(|owner| owner.split_once(" on ")) else {
+        return false;
+    };
+    let ours = self::owner();
+    if owner == ours {
+        return true;
+    }
+    if !ours.ends_with(&format!(" on {host}")) {
+        return true;
+    }
+    if Path::new("/proc/self").exists() {
+        return Path::new("/proc").join(pid).exists();
+    }
+    process::Command::new("kill")
+        .args(["-0", pid])
+        .stderr(Stdio::null())
+        .status()
+        .is_ok_and(|status| status.success())
+}
+fn hash_file(file: &str) -> Result<u64, Error> {
+    let mut reader = HashingReader { inner: fs::File::open(file)?, hasher: DefaultHasher::new() };
+    io::copy(&mut reader, &mut io::sink())?;
+    Ok(reader.hasher.finish())
+}
+fn with_context(error: &Error, context: &str) -> Error {
+    Error::new(error.kind(), format!("{context}: {error}"))
+}
+//reads about `CHUNK_SIZE` bytes, up to the end of a line so no character is cut,
+//decodes them and splits them into rows. with CRLF line endings the carriage returns
+//are dropped, otherwise they stay part of the row. once the end of the file is
+//reached also returns whether it ends in a line break and the hash of all its bytes
+fn read_chunk<R: Read>(reader: &mut BufReader<HashingReader<R>>, encoding: Encoding, line_ending: LineEnding) -> Result<Chunk, Error> {
+    let mut bytes = Vec::with_capacity(CHUNK_SIZE);
+    reader.by_ref().take(CHUNK_SIZE as u64).read_to_end(&mut bytes)?;
+    let newline = encoding.encode("\n");
+    if newline.len() == 1 {
+        reader.read_until(b'\n', &mut bytes)?;
+    } else {
+        //a line break only counts where a code unit starts
+        let mut byte = [0];
+        while !(bytes.len() % newline.len() == 0 && bytes.ends_with(&newline)) && reader.read(&mut byte)? > 0 {
+            bytes.push(byte[0]);
+        }
+    }
+    let mut builder = RopeBuilder::new();
+    for line in encoding.decode(&bytes).split_terminator('\n') {
+        let line = match line_ending {
+            LineEnding::Crlf => line.strip_suffix('\r').unwrap_or(line),
+            LineEnding::Lf => line,
+        };
+        builder.append(line);
+        builder.append("\n");
+    }
+    let end = if reader.fill_buf()?.is_empty() {
+        Some(FileEnd {
+            final_newline: bytes.is_empty() || bytes.ends_with(&newline),
+            hash: reader.get_ref().hasher.finish(),
+        })
+    } else {
+        None
+    };
+    Ok((builder.finish(), end))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn type_text(document: &mut Document, at: &mut Position, text: &str) {
+        for c in text.chars() {
+            let before = *at;
+            document.insert(at, c);
+            at.x += 1;
+            document.checkpoint(&before, at);
+        }
+    }
+    fn paste(document: &mut Document, at: &mut Position, text: &str) {
+        let before = *at;
+        *at = document.insert_str(at, text);
+        document.checkpoint(&before, at);
+    }
+    fn rows(document: &Document) -> Vec<String> {
+        (0..document.len()).map(|y| document.row(y).unwrap().as_str().to_string()).collect()
+    }
+    #[test]
+    fn measured_rows_follow_edits() {
+        let mut document = Document::default();
+        document.insert_str(&Position::default(), "one\ntwo\nthree");
+        assert_eq!(rows(&document), ["one", "two", "three"]);
+        document.insert(&Position { x: 3, y: 0 }, '!');
+        assert_eq!(rows(&document), ["one!", "two", "three"]);
+        document.insert(&Position { x: 1, y: 1 }, '\n');
+        assert_eq!(rows(&document), ["one!", "t", "wo", "three"]);
+        document.delete(&Position { x: 4, y: 0 });
+        assert_eq!(rows(&document), ["one!t", "wo", "three"]);
+        document.checkpoint(&Position::default(), &Position::default());
+        document.undo();
+        assert!(rows(&document).is_empty());
+        document.redo();
+        assert_eq!(rows(&document), ["one!t", "wo", "three"]);
+    }
+    fn test_file(name: &str, contents: &[u8]) -> String {
+        let file = std::env::temp_dir().join(format!("hecto-{}-{name}", process::id()));
+        fs::write(&file, contents).unwrap();
+        file.to_string_lossy().to_string()
+    }
+    fn swap_test_file(name: &str) -> String {
+        test_file(name, b"saved\n")
+    }
+    //opens `contents`, types an x at the start and saves, returning what was written
+    fn edit_and_save(name: &str, contents: &[u8]) -> Vec<u8> {
+        let file = test_file(name, contents);
+        let mut document = Document::open(&file).unwrap();
+        document.finish_loading().unwrap();
+        document.insert(&Position::default(), 'x');
+        document.save(false).unwrap();
+        let saved = fs::read(&file).unwrap();
+        fs::remove_file(file).unwrap();
+        saved
+    }
+    #[test]
+    fn saving_keeps_line_endings() {
+        assert_eq!(edit_and_save("crlf", b"one\r\ntwo\r\n"), b"xone\r\ntwo\r\n");
+        assert_eq!(edit_and_save("lf", b"one\ntwo\n"), b"xone\ntwo\n");
+    }
+    #[test]
+    fn saving_keeps_a_missing_final_newline() {
+        assert_eq!(edit_and_save("no-newline", b"one\ntwo"), b"xone\ntwo");
+        assert_eq!(edit_and_save("crlf-no-newline", b"one\r\ntwo"), b"xone\r\ntwo");
+    }
+    #[test]
+    fn saving_keeps_permissions_and_backs_up() {
+        use std::os::unix::fs::PermissionsExt;
+        let file = test_file("backup", b"old\n");
+        fs::set_permissions(&file, fs::Permissions::from_mode(0o640)).unwrap();
+        let mut document = Document::open(&file).unwrap();
+        document.insert(&Position::default(), 'x');
+        document.save(true).unwrap();
+        assert_eq!(fs::read_to_string(&file).unwrap(), "xold\n");
+        assert_eq!(fs::metadata(&file).unwrap().permissions().mode() & 0o777, 0o640);
+        let backup = format!("{file}~");
+        assert_eq!(fs::read_to_string(&backup).unwrap(), "old\n");
+        fs::remove_file(backup).unwrap();
+        fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn failed_saves_leave_the_file_and_no_temporary_file() {
+        let file = test_file("failed", b"old\n");
+        //the backup cannot be copied over a directory
+        let backup = format!("{file}~");
+        fs::create_dir(&backup).unwrap();
+        let mut document = Document::open(&file).unwrap();
+        document.insert(&Position::default(), 'x');
+        assert!(document.save(true).is_err());
+        assert!(document.is_dirty());
+        assert_eq!(fs::read_to_string(&file).unwrap(), "old\n");
+        let path = Path::new(&file);
+        let temp = path.with_file_name(format!(".{}.hecto-{}", path.file_name().unwrap().to_string_lossy(), process::id()));
+        assert!(!temp.exists());
+        fs::remove_dir(backup).unwrap();
+        fs::remove_file(file).unwrap();
+    }
+    fn wait_for_swap(document: &mut Document) {
+        while document.swap_write.as_ref().is_some_and(|write| !write.is_finished()) {
+            thread::yield_now();
+        }
+        document.write_swap().unwrap();
+    }
+    #[test]
+    fn swap_files_of_running_sessions_are_left_alone() {
+        let file = swap_test_file("live");
+        let mut first = Document::open(&file).unwrap();
+        first.insert(&Position::default(), 'x');
+        first.write_swap().unwrap();
+        wait_for_swap(&mut first);
+        let mut second = Document::open(&file).unwrap();
+        assert!(matches!(second.read_swap().unwrap(), Some(Swap::Live(owner)) if owner == self::owner()));
+        second.insert(&Position::default(), 'y');
+        second.write_swap().unwrap();
+        second.remove_swap();
+        let swap_file = first.swap_file().unwrap();
+        assert!(fs::read_to_string(&swap_file).unwrap().ends_with("\nxsaved\n"));
+        first.remove_swap();
+        assert!(!swap_file.exists());
+        fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn swap_files_of_ended_sessions_are_offered() {
+        let file = swap_test_file("stale");
+        let mut document = Document::open(&file).unwrap();
+        let swap_file = document.swap_file().unwrap();
+        let host = self::owner().split_once(" on ").unwrap().1.to_string();
+        fs::write(&swap_file, format!("{SWAP_HEADER}process {} on {host}\nrecovered\n", u32::MAX)).unwrap();
+        assert!(matches!(document.read_swap().unwrap(), Some(Swap::Stale(text)) if text == "recovered"));
+        document.remove_swap();
+        assert!(swap_file.exists());
+        document.discard_swap();
+        assert!(!swap_file.exists());
+        fs::remove_file(file).unwrap();
+    }
+    #[test]
+    fn undoes_a_paste_apart_from_the_typing_before_it() {
+        let mut document = Document::default();
+        let mut at = Position::default();
+        type_text(&mut document, &mut at, "ab");
+        paste(&mut document, &mut at, "PASTED\nLINE");
+        assert_eq!(document.text(), "abPASTED\nLINE");
+        document.undo();
+        assert_eq!(document.text(), "ab");
+        document.undo();
+        assert_eq!(document.text(), "");
+    }
+    #[test]
+    fn undoes_typing_apart_from_a_single_character_paste() {
+        let mut document = Document::default();
+        let mut at = Position::default();
+        type_text(&mut document, &mut at, "ab");
+        paste(&mut document, &mut at, "x");
+        type_text(&mut document, &mut at, "cd");
+        document.undo();
+        assert_eq!(document.text(), "abx");
+        document.undo();
+        assert_eq!(document.text(), "ab");
+        document.redo();
+        document.redo();
+        assert_eq!(document.text(), "abxcd");
+    }
+}
@@ -1,29 +1,58 @@
 use crate::Terminal;
 use crate::Document;
+use crate::document::Swap;
+use crate::LineEnding;
+use crate::Encoding;
 use crate::Row;
+use crate::Config;
+use crate::args::Args;
+use crate::clipboard::Clipboard;
+use crate::highlighting;
+use crate::layout::{Layout, Rect, SplitDirection};
 use std::cmp;
+use std::mem;
+use std::io::{self, IsTerminal};
+use std::iter;
+use unicode_segmentation::{GraphemeCursor, UnicodeSegmentation};
+use unicode_width::UnicodeWidthStr;
 use std::time::{Duration, Instant};
-use crossterm::terminal::enable_raw_mode;
 use crossterm::event::{
     poll, read,
     Event,
     KeyModifiers, KeyCode, KeyEvent,
 };
 use crossterm::style::Color;
+use regex::Regex;
 const STATUS_FG_COLOR: Color = Color::Rgb { r: 63, g: 63, b:63 };
 const STATUS_BG_COLOR: Color = Color::Rgb { r: 239, g: 239, b:239 };
-use std::env;
 const VERSION: &str = env!("CARGO_PKG_VERSION");
-#[derive(Debug)]
+const QUIT_TIMES: u8 = 3;
+//how often unsaved changes are written to swap files
+const SWAP_INTERVAL: Duration = Duration::from_secs(4);
+//how often the file of the current buffer is looked at for changes by other programs
+const DISK_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const LINE_NUMBER_WIDTH: usize = 4;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
 pub struct Position {
     pub x: usize,
     pub y: usize,
 }
-impl Default for Position {
-    fn default() -> Self {
-        Position { x: 0, y: 0 }
+//positions are ordered as they appear in the document, row first
+impl Ord for Position {
+    fn cmp(&self, other: &Self) -> cmp::Ordering {
+        (self.y, self.x).cmp(&(other.y, other.x))
     }
 }
+impl PartialOrd for Position {
+    fn partial_cmp(&self, other: &Self) -> Option<cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum SearchDirection {
+    Forward,
+    Backward,
+}
 struct StatusMessage {
     text: String,
     time: Instant,
@@ -36,6 +65,22 @@ impl StatusMessage {
         }
     }
 } 
+//a document that is open but not the one being edited, along with
+//where its cursor and view were left
+#[derive(Default)]
+struct Buffer {
+    document: Document,
+    cursor_position: Position,
+    offset: Position,
+}
+//a window onto one of the buffers, each with its own cursor and scroll offset.
+//those of the active view live in the editor itself
+struct View {
+    buffer: usize,
+    cursor_position: Position,
+    offset: Position,
+    area: Rect,
+}
 pub struct Editor {
     terminal: Terminal,
     quit: bool,
@@ -43,184 +88,409 @@ pub struct Editor {
     document: Document,
     offset: Position,
     status_message: StatusMessage,
+    highlighted_word: Option<String>,
+    quit_times: u8,
+    //whether every opened file is read-only
+    readonly: bool,
+    last_swap: Instant,
+    last_disk_check: Instant,
+    config: Config,
+    //the other end of the selection, the cursor being the end that moves
+    selection_anchor: Option<Position>,
+    clipboard: Clipboard,
+    //every open document, the slot of the current one is empty
+    //while it lives in `document`
+    buffers: Vec<Buffer>,
+    current_buffer: usize,
+    views: Vec<View>,
+    active_view: usize,
+    layout: Layout,
+    separators: Vec<(SplitDirection, Rect)>,
 }
 trait InputType {
     fn is_ctrl(&self, key: char) -> bool;
+    fn is_alt(&self, key: char) -> bool;
     fn is_shift_ctrl(&self, key: char) -> bool;
     fn is_movement(&self) -> bool;
+    fn is_shift_movement(&self) -> bool;
+    fn is_edit(&self) -> bool;
+    fn is_edit_shortcut(&self) -> bool;
 }
 impl InputType for KeyEvent {
     fn is_ctrl(&self, key: char) -> bool {
         (self.code == KeyCode::Char(key)) && (self.modifiers == KeyModifiers::CONTROL)
     }
+    fn is_alt(&self, key: char) -> bool {
+        (self.code == KeyCode::Char(key)) && (self.modifiers == KeyModifiers::ALT)
+    }
     fn is_shift_ctrl(&self, key: char) -> bool {
         (self.code == KeyCode::Char(key)) && (self.modifiers == (KeyModifiers::CONTROL | KeyModifiers::SHIFT))
     }
     fn is_movement(&self) -> bool {
-        (
-            self.code == KeyCode::Up
-            || self.code == KeyCode::Down
-            || self.code == KeyCode::Left
-            || self.code == KeyCode::Right
-            || self.code == KeyCode::PageUp
-            || self.code == KeyCode::PageDown
-            || self.code == KeyCode::Home
-            || self.code == KeyCode::End
-        ) && (
+        is_movement_key(self.code) && (
             self.modifiers == KeyModifiers::NONE
         )
     }
+    fn is_shift_movement(&self) -> bool {
+        is_movement_key(self.code) && (
+            self.modifiers == KeyModifiers::SHIFT
+        )
+    }
+    //keys that replace the selection when one is active
+    fn is_edit(&self) -> bool {
+        match self.code {
+            KeyCode::Char(_) => !self.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT),
+            KeyCode::Enter | KeyCode::Backspace | KeyCode::Delete | KeyCode::Tab => true,
+            _ => false,
+        }
+    }
+    //shortcuts that change the document or write it
+    fn is_edit_shortcut(&self) -> bool {
+        ['s', 'e', 'z', 'y', 'r', 'x', 'v'].iter().any(|&key| self.is_ctrl(key))
+            || ['c', 'e'].iter().any(|&key| self.is_alt(key))
+            || self.is_shift_ctrl('s')
+    }
+}
+fn is_movement_key(code: KeyCode) -> bool {
+    code == KeyCode::Up
+    || code == KeyCode::Down
+    || code == KeyCode::Left
+    || code == KeyCode::Right
+    || code == KeyCode::PageUp
+    || code == KeyCode::PageDown
+    || code == KeyCode::Home
+    || code == KeyCode::End
 }
 impl Editor {
     pub fn run(&mut self) -> crossterm::Result<()> {
-        enable_raw_mode()?;
+        let _guard = Terminal::enter()?;
+        self.recover_swap_files();
         loop {
-            if let crossterm::Result::Err(error) = self.refresh_screen() {
-                ded(error);
-            }
+            self.load_documents();
+            self.write_swap_files();
+            self.check_disk_changes();
+            self.refresh_screen()?;
             if self.quit {
                 break;
             }
             if !poll(Duration::from_millis(100))? {
                 continue;
             }
-            if let crossterm::Result::Err(error) = self.process_event() {
-                ded(error);
-            }
+            self.process_event()?;
+        }
+        //quitting was confirmed, so whatever was not saved is thrown away
+        for document in iter::once(&mut self.document).chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document)) {
+            document.remove_swap();
         }
         Ok(())
     }
-    pub fn default() -> Self {
-        let args: Vec<String> = env::args().collect();
-        let mut initial_status = String::from("HELP: Ctrl+Q = quit | Ctrl+S = save");
-        let document = if args.len() > 1 {
-            let file_name = &args[1];
-            let doc = Document::open(&file_name);
-            if doc.is_ok() {
-                doc.unwrap()
+    //opens the files named in `args`, those that cannot be opened are reported
+    //in the message bar. fails only when the terminal cannot be set up
+    pub fn new(args: &Args) -> io::Result<Self> {
+        let mut initial_status = String::from("HELP: Ctrl+Q = quit | Ctrl+S = save | Ctrl+F = find | Ctrl+R = replace | Ctrl+X/C/V = cut/copy/paste | Ctrl+Z = undo | Ctrl+Y = redo | Ctrl+O = open | Ctrl+N/P = next/previous buffer | Ctrl+B = buffers | Ctrl+W = close | Ctrl+E/Alt+E = line endings | Alt+C = encoding | Alt+R = reload | Alt+H/V = split | Alt+O = other view | Alt+X = close view");
+        let mut buffers = Vec::new();
+        let mut failed = Vec::new();
+        for file in &args.files {
+            let document = if file.is_stdin() {
+                if io::stdin().is_terminal() {
+                    eprintln!("hecto: reading standard input, end it with Ctrl+D");
+                }
+                //the buffer has no name, so saving it asks for one
+                Document::from_reader(io::stdin())
             } else {
-                initial_status = format!("ERR: Could not open file: {}", file_name);
-                Document::default()
-            }
-        } else {
-            Document::default()
-        };
-        Self {
-            terminal: Terminal::default().expect("something went wrong while initializing terminal"),
+                Document::open(&file.name)
+            };
+            let Ok(mut document) = document else {
+                failed.push(file.name.as_str());
+                continue;
+            };
+            document.set_readonly(args.readonly);
+            let cursor_position = file.position.map_or_else(Position::default, |position| {
+                if position.y >= document.len() {
+                    //the location may lie beyond what was read so far
+                    let _ = document.finish_loading();
+                }
+                let y = cmp::min(position.y, document.len());
+                let x = cmp::min(position.x, document.row(y).map_or(0, |row| row.len()));
+                Position { x, y }
+            });
+            buffers.push(Buffer { document, cursor_position, ..Buffer::default() });
+        }
+        if !failed.is_empty() {
+            initial_status = format!("ERR: Could not open file: {}", failed.join(", "));
+        }
+        if buffers.is_empty() {
+            buffers.push(Buffer::default());
+        }
+        let Buffer { document, cursor_position, .. } = mem::take(&mut buffers[0]);
+        let config = Config::from_env();
+        let mut editor = Self {
+            terminal: Terminal::new()?,
             quit: false,
             document,
-            cursor_position: Position::default(),
+            cursor_position,
             offset: Position::default(),
-            status_message: StatusMessage::from(initial_status)
-        }
+            status_message: StatusMessage::from(initial_status),
+            highlighted_word: None,
+            quit_times: QUIT_TIMES,
+            readonly: args.readonly,
+            last_swap: Instant::now(),
+            last_disk_check: Instant::now(),
+            selection_anchor: None,
+            clipboard: Clipboard::new(&config),
+            config,
+            buffers,
+            current_buffer: 0,
+            views: vec![View {
+                buffer: 0,
+                cursor_position: Position::default(),
+                offset: Position::default(),
+                area: Rect::default(),
+            }],
+            active_view: 0,
+            layout: Layout::View(0),
+            separators: Vec::new(),
+        };
+        editor.update_layout();
+        editor.scroll();
+        Ok(editor)
     }
-    fn refresh_screen(&self) -> std::io::Result<()> {
+    fn refresh_screen(&mut self) -> std::io::Result<()> {
         Terminal::hide_cursor();
         Terminal::move_cursor(&Position{ x: 0, y: 0 });
         if self.quit {
             Terminal::clear_screen();
             println!("Goodbye \r");
         } else {
-            self.draw_rows();
+            self.highlight_views();
+            self.draw_views();
+            Terminal::move_cursor(&Position { x: 0, y: self.terminal.size().rows as usize });
             self.draw_status_bar();
             self.draw_message_bar();
-            Terminal::move_cursor(&Position { x: self.cursor_position.x.saturating_sub(self.offset.x).saturating_add(4),
-                                              y: self.cursor_position.y.saturating_sub(self.offset.y), });
+            let area = self.area();
+            Terminal::move_cursor(&Position { x: area.x + self.cursor_colum().saturating_sub(self.offset.x).saturating_add(LINE_NUMBER_WIDTH),
+                                              y: area.y + self.cursor_position.y.saturating_sub(self.offset.y), });
         }
         Terminal::show_cursor();
         Terminal::flush()
     }
-    fn welcome_messages(&self) -> String {
-        let mut welcome_message = format!("Hecto editor -- version {}", VERSION);
-        let colums = self.terminal.size().colums as usize;                
+    fn welcome_messages(colums: usize) -> String {
+        let mut welcome_message = format!("Hecto editor -- version {VERSION}");
         let len = welcome_message.len();
         let padding = colums.saturating_sub(len) / 2;
         let spaces = " ".repeat(padding.saturating_sub(1));
-        welcome_message = format!("{}{}", spaces, welcome_message);
-        welcome_message.truncate(colums);
+        welcome_message = format!("{spaces}{welcome_message}");
+        truncate_to_width(&mut welcome_message, colums);
         welcome_message
     }
-    fn draw_rows(&self) {
-        let visible_rows = self.terminal.size().rows;
-        for terminal_row in 0..visible_rows {
-            Terminal::clear_current_line();
-            if self.document.is_empty() && terminal_row == visible_rows/2 {
-                println!("{:3}{}\r", terminal_row + 1, self.welcome_messages());
-            } else if let Some(row) = self.document.row(terminal_row as usize + self.offset.y) {
-                println!("{:3} {}\r", terminal_row as usize + self.offset.y + 1, self.render_row(row));
+    //brings the comment state of every document shown up to date, documents that
+    //are not current are highlighted in their buffer slot
+    fn highlight_views(&mut self) {
+        for index in 0..self.views.len() {
+            let (buffer, offset) = if index == self.active_view {
+                (self.current_buffer, self.offset)
+            } else {
+                (self.views[index].buffer, self.views[index].offset)
+            };
+            let until = offset.y.saturating_add(self.views[index].area.height);
+            let document = if buffer == self.current_buffer {
+                &mut self.document
+            } else {
+                &mut self.buffers[buffer].document
+            };
+            document.highlight(until);
+        }
+    }
+    fn draw_views(&self) {
+        for (index, view) in self.views.iter().enumerate() {
+            if index == self.active_view {
+                self.draw_view(&self.document, &self.offset, self.selection(), &view.area);
             } else {
-                println!("{:3} \r", terminal_row + 1);
+                self.draw_view(self.buffer_document(view.buffer), &view.offset, None, &view.area);
             }
         }
+        for (direction, rect) in &self.separators {
+            let line = match direction {
+                SplitDirection::Horizontal => "\u{2500}",
+                SplitDirection::Vertical => "\u{2502}",
+            };
+            for y in rect.y..rect.y + rect.height {
+                Terminal::move_cursor(&Position { x: rect.x, y });
+                print!("{}", line.repeat(rect.width));
+            }
+        }
+    }
+    //draws the rows of `document` that fit into `area`, each line padded
+    //to the full width so nothing from the previous frame is left over
+    fn draw_view(&self, document: &Document, offset: &Position, selection: Option<(Position, Position)>, area: &Rect) {
+        let text_width = area.width.saturating_sub(LINE_NUMBER_WIDTH);
+        for terminal_row in 0..area.height {
+            Terminal::move_cursor(&Position { x: area.x, y: area.y + terminal_row });
+            let y = terminal_row + offset.y;
+            let mut line_number = if document.is_empty() && terminal_row == area.height / 2 {
+                format!("{:3}{}", terminal_row + 1, Self::welcome_messages(area.width.saturating_sub(3)))
+            } else if y < document.len() {
+                format!("{:3} ", y + 1)
+            } else {
+                format!("{:3} ", terminal_row + 1)
+            };
+            truncate_to_width(&mut line_number, area.width);
+            print!("{line_number}");
+            let mut used = line_number.width();
+            if let Some(row) = document.highlighted_row(y, self.highlighted_word.as_deref()) {
+                used += self.draw_row(&row, y, offset.x, text_width, selection);
+            }
+            print!("{}", " ".repeat(area.width.saturating_sub(used)));
+        }
+    }
+    //prints the part of row `y` between colums `start` and `start + width`,
+    //switching colors whenever the highlight class changes. search matches are
+    //drawn on a highlighted background and the selection in reverse video.
+    //returns the number of colums printed
+    fn draw_row(&self, row: &Row, y: usize, start: usize, width: usize, selection: Option<(Position, Position)>) -> usize {
+        let selected = match selection {
+            Some((first, last)) if first.y <= y && y <= last.y => {
+                let from = if y == first.y { first.x } else { 0 };
+                let to = if y == last.y { last.x } else { row.len() };
+                from..to
+            },
+            _ => 0..0,
+        };
+        let mut used = 0;
+        for (hl_type, selected, text) in row.render(start, start + width, self.config.tab_width, &selected) {
+            if hl_type == highlighting::Type::Match {
+                Terminal::set_bg_color(hl_type.to_color());
+            } else {
+                Terminal::set_fg_color(hl_type.to_color());
+            }
+            if selected {
+                Terminal::set_reverse();
+            }
+            print!("{text}");
+            used += text.width();
+            Terminal::reset_color();
+            if selected {
+                Terminal::reset_attributes();
+            }
+        }
+        used
     }
-    pub fn render_row(&self, row: &Row) -> String {
-        let offset = self.offset.x;
-        let start = 0;
-        let end = (self.terminal.size().colums as usize).saturating_sub(4);
-        row.render(start + offset, end + offset)
+    //area of the active view
+    fn area(&self) -> Rect {
+        self.views[self.active_view].area
+    }
+    //colums left for text after the line numbers
+    fn text_width(&self) -> usize {
+        self.area().width.saturating_sub(LINE_NUMBER_WIDTH)
+    }
+    //screen colum of the cursor inside the document, wide graphemes count double
+    fn cursor_colum(&self) -> usize {
+        self.document
+            .row(self.cursor_position.y)
+            .map_or(0, |row| row.width_until(self.cursor_position.x, self.config.tab_width))
     }
     fn draw_status_bar(&self) {
         let colums = self.terminal.size().colums as usize;
         let mut status;
         let mut file_name = "[Untitled]".to_string();
         if let Some(name) = &self.document.file {
-            file_name = name.clone();
-            file_name = "  ".to_string() + &file_name[(file_name.len().saturating_sub(18))..file_name.len()].to_string();
+            let skipped = name.chars().count().saturating_sub(18);
+            file_name = "  ".to_string() + &name.chars().skip(skipped).collect::<String>();
+        }
+        let modified_indicator = if self.document.is_dirty() {
+            " (modified)"
+        } else if self.document.is_readonly() {
+            " (read-only)"
+        } else {
+            ""
+        };
+        if self.buffers.len() > 1 {
+            file_name = format!("[{}/{}]{}", self.current_buffer + 1, self.buffers.len(), file_name);
         }
-        status = format!("{} | {} lines", file_name, self.document.len());
+        let loading_indicator = if self.document.is_loading() {
+            " (loading)"
+        } else {
+            ""
+        };
+        status = format!("{}{} | {} lines{}", file_name, modified_indicator, self.document.len(), loading_indicator);
         let line_indicator = format!(
-            "{}/{}",
+            "{} | {} | {}{} | {}/{}",
+            self.document.file_type(),
+            self.encoding_name(),
+            self.document.line_ending().name(),
+            if self.document.has_final_newline() { "" } else { " [noeol]" },
             self.cursor_position.y.saturating_add(1),
             self.document.len(),
-        ); 
-        let total_len = status.len() + line_indicator.len();
+        );
+        let total_len = status.width() + line_indicator.width();
         if colums > total_len {
            status.push_str(&" ".repeat(colums - total_len));
         }
-        status = format!("{}{}", status, line_indicator);
-        status.truncate(colums);
+        status = format!("{status}{line_indicator}");
+        truncate_to_width(&mut status, colums);
         Terminal::set_bg_color(STATUS_BG_COLOR);
         Terminal::set_fg_color(STATUS_FG_COLOR);
-        println!("{}\r", status);
+        println!("{status}\r");
         Terminal::reset_color();
     }
     fn draw_message_bar(&self) {
+        Terminal::move_cursor(&Position { x: 0, y: self.terminal.size().rows as usize + 1 });
         Terminal::clear_current_line();
         let message = &self.status_message;
-        if Instant::now() - message.time < Duration::new(5, 0) {
+        if message.time.elapsed() < Duration::new(5, 0) {
             let mut text = message.text.clone();
-            text.truncate(self.terminal.size().colums as usize);
-            print!("{}", text);
+            truncate_to_width(&mut text, self.terminal.size().colums as usize);
+            print!("{text}");
         }
-    } 
+    }
     fn process_event(&mut self) -> crossterm::Result<()> {
-        if let Event::Key(keyevent) = read()? {
-            if keyevent.is_ctrl('q') {
-                self.quit = true;
+        let event = read()?;
+        if let Event::Resize(colums, rows) = event {
+            self.resize(colums, rows);
+        }
+        if let Event::Paste(text) = &event {
+            if self.refuse_edit() {
+                return Ok(());
             }
-            if keyevent.is_ctrl('s') {
-                if self.document.file.is_none() {
-                    self.document.file = self.promt("Save as: ").unwrap();
-                }
-                if self.document.file.is_none() {
-                    self.status_message = StatusMessage::from("Save aborted".to_string());
-                } else {
-                    self.save();
+            let cursor_before = self.cursor_position;
+            self.delete_selection();
+            self.insert_pasted(text);
+            self.document.checkpoint(&cursor_before, &self.cursor_position);
+        }
+        if let Event::Key(keyevent) = event {
+            let cursor_before = self.cursor_position;
+            if keyevent.is_ctrl('q') {
+                let dirty = self.document.is_dirty() || self.buffers.iter().any(|buffer| buffer.document.is_dirty());
+                if self.quit_times > 0 && dirty {
+                    self.status_message = StatusMessage::from(format!(
+                        "WARNING! There are unsaved changes. Press Ctrl+Q {} more times to quit.",
+                        self.quit_times
+                    ));
+                    self.quit_times -= 1;
+                    return Ok(());
                 }
+                self.quit = true;
+            } else if self.quit_times < QUIT_TIMES {
+                self.quit_times = QUIT_TIMES;
+                self.status_message = StatusMessage::from(String::new());
             }
-            if keyevent.is_shift_ctrl('s') {
-                self.document.file = self.promt("Save as: ").unwrap();
-                if self.document.file.is_none() {
-                    self.status_message = StatusMessage::from("Save aborted".to_string());
-                } else {
-                    self.save();
-                }
+            if (keyevent.is_edit() || keyevent.is_edit_shortcut()) && self.refuse_edit() {
+                return Ok(());
             }
+            self.process_shortcut(&keyevent);
             if keyevent.is_movement() {
+                self.selection_anchor = None;
                 self.move_cursor(keyevent.code);
             }
+            if keyevent.is_shift_movement() {
+                if self.selection_anchor.is_none() {
+                    self.selection_anchor = Some(self.cursor_position);
+                }
+                self.move_cursor(keyevent.code);
+            }
+            let deleted_selection = keyevent.is_edit() && self.delete_selection();
             match keyevent.code {
-                KeyCode::Char(character) => {
+                KeyCode::Char(character) if !keyevent.modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
                     self.document.insert(&self.cursor_position, character);
                     self.move_cursor(KeyCode::Right);
                 },
@@ -229,22 +499,429 @@ impl Editor {
                     self.move_cursor(KeyCode::Down);
                     self.move_cursor(KeyCode::Home);
                 },
-                KeyCode::Backspace => {
-                    if self.cursor_position.x > 0 || self.cursor_position.y > 0 {
-                        self.move_cursor(KeyCode::Left);
-                        self.document.delete(&self.cursor_position);
-                    }
+                KeyCode::Backspace if !deleted_selection && (self.cursor_position.x > 0 || self.cursor_position.y > 0) => {
+                    self.move_cursor(KeyCode::Left);
+                    self.document.delete(&self.cursor_position);
                 },
-                KeyCode::Delete => {
+                KeyCode::Delete if !deleted_selection => {
                     self.document.delete(&self.cursor_position);
                 },
+                KeyCode::Tab => self.insert_tab(),
                 _ => (),
             }
+            self.document.checkpoint(&cursor_before, &self.cursor_position);
         }
         Ok(())
     }
+    //tells why nothing happens when the current document is read-only
+    fn refuse_edit(&mut self) -> bool {
+        if self.document.is_readonly() {
+            self.status_message = StatusMessage::from("The buffer is read-only".to_string());
+        }
+        self.document.is_readonly()
+    }
+    //commands bound to ctrl combinations
+    fn process_shortcut(&mut self, keyevent: &KeyEvent) {
+        if keyevent.is_ctrl('s') {
+            self.save_as(self.document.file.is_none());
+        }
+        if keyevent.is_shift_ctrl('s') {
+            self.save_as(true);
+        }
+        if keyevent.is_ctrl('e') {
+            let line_ending = match self.document.line_ending() {
+                LineEnding::Lf => LineEnding::Crlf,
+                LineEnding::Crlf => LineEnding::Lf,
+            };
+            self.document.set_line_ending(line_ending);
+            self.status_message = StatusMessage::from(format!("Line endings will be saved as {}", line_ending.name()));
+        }
+        if keyevent.is_alt('c') {
+            self.change_encoding();
+        }
+        if keyevent.is_alt('e') {
+            let final_newline = !self.document.has_final_newline();
+            self.document.set_final_newline(final_newline);
+            self.status_message = StatusMessage::from(if final_newline {
+                "The file will end with a line break".to_string()
+            } else {
+                "The file will end without a line break".to_string()
+            });
+        }
+        if keyevent.is_ctrl('f') {
+            self.selection_anchor = None;
+            self.search();
+        }
+        if keyevent.is_ctrl('z') {
+            self.undo(false);
+        }
+        if keyevent.is_ctrl('y') {
+            self.undo(true);
+        }
+        if keyevent.is_ctrl('r') {
+            self.selection_anchor = None;
+            self.replace();
+        }
+        if keyevent.is_ctrl('c') {
+            self.copy(false);
+        }
+        if keyevent.is_ctrl('x') {
+            self.copy(true);
+        }
+        if keyevent.is_ctrl('v') {
+            self.paste();
+        }
+        if keyevent.is_ctrl('n') {
+            self.switch_buffer((self.current_buffer + 1) % self.buffers.len());
+        }
+        if keyevent.is_ctrl('p') {
+            self.switch_buffer((self.current_buffer + self.buffers.len() - 1) % self.buffers.len());
+        }
+        if keyevent.is_ctrl('b') {
+            self.pick_buffer();
+        }
+        if keyevent.is_ctrl('o') {
+            self.open_file();
+        }
+        if keyevent.is_ctrl('w') {
+            self.close_buffer();
+        }
+        if keyevent.is_alt('h') {
+            self.split_view(SplitDirection::Horizontal);
+        }
+        if keyevent.is_alt('v') {
+            self.split_view(SplitDirection::Vertical);
+        }
+        if keyevent.is_alt('o') {
+            let order = self.layout.views();
+            let position = order.iter().position(|&view| view == self.active_view).unwrap_or(0);
+            self.focus_view(order[(position + 1) % order.len()]);
+        }
+        if keyevent.is_alt('x') {
+            self.close_view();
+        }
+        if keyevent.is_alt('r') {
+            self.ask_reload();
+        }
+    }
+    //recomputes the area of every view from the terminal size, callers changing
+    //it on screen clear the screen so the next refresh redraws everything
+    fn update_layout(&mut self) {
+        let screen = Rect {
+            x: 0,
+            y: 0,
+            width: self.terminal.size().colums as usize,
+            height: self.terminal.size().rows as usize,
+        };
+        let mut areas = Vec::new();
+        let mut separators = Vec::new();
+        self.layout.areas(screen, &mut areas, &mut separators);
+        for (view, area) in areas {
+            self.views[view].area = area;
+        }
+        self.separators = separators;
+    }
+    //splits the active view in two, both showing the current buffer,
+    //and moves to the new half
+    fn split_view(&mut self, direction: SplitDirection) {
+        let area = self.area();
+        let too_small = match direction {
+            SplitDirection::Horizontal => area.height < 5,
+            SplitDirection::Vertical => area.width < 2 * (LINE_NUMBER_WIDTH + 8) + 1,
+        };
+        if too_small {
+            self.status_message = StatusMessage::from("Not enough room to split".to_string());
+            return;
+        }
+        self.views.push(View {
+            buffer: self.current_buffer,
+            cursor_position: self.cursor_position,
+            offset: self.offset,
+            area,
+        });
+        let new_view = self.views.len() - 1;
+        self.layout.split(self.active_view, new_view, direction);
+        self.update_layout();
+        Terminal::clear_screen();
+        self.focus_view(new_view);
+    }
+    fn close_view(&mut self) {
+        if self.views.len() == 1 {
+            self.status_message = StatusMessage::from("Cannot close the last view".to_string());
+            return;
+        }
+        let closed = self.active_view;
+        self.layout.remove(closed);
+        self.views.remove(closed);
+        self.update_layout();
+        Terminal::clear_screen();
+        self.activate_view(closed.saturating_sub(1));
+    }
+    //leaves the cursor and offset in the active view and moves to another one
+    fn focus_view(&mut self, index: usize) {
+        if index == self.active_view || index >= self.views.len() {
+            return;
+        }
+        let view = &mut self.views[self.active_view];
+        view.cursor_position = self.cursor_position;
+        view.offset = self.offset;
+        self.activate_view(index);
+    }
+    fn activate_view(&mut self, index: usize) {
+        self.active_view = index;
+        let buffer = self.views[index].buffer;
+        if buffer != self.current_buffer {
+            self.switch_buffer(buffer);
+        }
+        self.cursor_position = self.views[index].cursor_position;
+        self.offset = self.views[index].offset;
+        self.selection_anchor = None;
+        //another view may have shortened the document meanwhile
+        self.clamp_cursor();
+        self.scroll();
+    }
+    //moves the cursor back into the document
+    fn clamp_cursor(&mut self) {
+        self.cursor_position.y = cmp::min(self.cursor_position.y, self.document.len());
+        self.cursor_position.x = cmp::min(
+            self.cursor_position.x,
+            self.document.row(self.cursor_position.y).map_or(0, |row| row.len()),
+        );
+    }
+    //puts the current document back into its buffer slot and
+    //makes the buffer at `index` the one being edited
+    fn switch_buffer(&mut self, index: usize) {
+        if index == self.current_buffer || index >= self.buffers.len() {
+            return;
+        }
+        self.buffers[self.current_buffer] = Buffer {
+            document: mem::take(&mut self.document),
+            cursor_position: self.cursor_position,
+            offset: self.offset,
+        };
+        self.current_buffer = index;
+        self.views[self.active_view].buffer = index;
+        self.load_buffer();
+    }
+    fn load_buffer(&mut self) {
+        let buffer = mem::take(&mut self.buffers[self.current_buffer]);
+        self.document = buffer.document;
+        self.cursor_position = buffer.cursor_position;
+        self.offset = buffer.offset;
+        self.selection_anchor = None;
+        self.scroll();
+    }
+    fn buffer_document(&self, index: usize) -> &Document {
+        if index == self.current_buffer {
+            &self.document
+        } else {
+            &self.buffers[index].document
+        }
+    }
+    fn buffer_name(&self, index: usize) -> String {
+        let document = self.buffer_document(index);
+        let name = document.file.clone().unwrap_or_else(|| "[Untitled]".to_string());
+        if document.is_dirty() {
+            format!("{name}*")
+        } else {
+            name
+        }
+    }
+    //lists the buffers in the message bar and switches to the one
+    //picked by its number or by part of its name
+    fn pick_buffer(&mut self) {
+        let list: Vec<String> = (0..self.buffers.len())
+            .map(|index| format!("{}:{}", index + 1, self.buffer_name(index)))
+            .collect();
+        let Some(choice) = self.promt(&format!("{} | Buffer: ", list.join(" ")), |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let index = match choice.trim().parse::<usize>() {
+            Ok(number) => number.checked_sub(1),
+            Err(_) => (0..self.buffers.len()).find(|&index| self.buffer_name(index).contains(choice.trim())),
+        };
+        match index {
+            Some(index) if index < self.buffers.len() => self.switch_buffer(index),
+            _ => self.status_message = StatusMessage::from(format!("No buffer matches: {choice}")),
+        }
+    }
+    //opens a file into a new buffer, or switches to it when it is already open
+    fn open_file(&mut self) {
+        let Some(file_name) = self.promt("Open: ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        if file_name.is_empty() {
+            return;
+        }
+        let open = (0..self.buffers.len())
+            .find(|&index| self.buffer_document(index).file.as_deref() == Some(file_name.as_str()));
+        if let Some(index) = open {
+            self.switch_buffer(index);
+            return;
+        }
+        match Document::open(&file_name) {
+            Ok(mut document) => {
+                document.set_readonly(self.readonly);
+                self.buffers.push(Buffer { document, ..Buffer::default() });
+                self.switch_buffer(self.buffers.len() - 1);
+            },
+            Err(_) => self.status_message = StatusMessage::from(format!("ERR: Could not open file: {file_name}")),
+        }
+    }
+    //closes the current buffer, asking first when it has unsaved changes.
+    //closing the last one leaves an empty buffer behind
+    fn close_buffer(&mut self) {
+        if self.document.is_dirty() {
+            let answer = self.confirm("Buffer has unsaved changes. Close anyway? (y/n)").unwrap_or(None);
+            self.status_message = StatusMessage::from(String::new());
+            if answer != Some('y') {
+                return;
+            }
+        }
+        self.document.remove_swap();
+        let removed = self.current_buffer;
+        if self.buffers.len() == 1 {
+            self.buffers[0] = Buffer::default();
+        } else {
+            self.buffers.remove(removed);
+            self.current_buffer = cmp::min(removed, self.buffers.len() - 1);
+        }
+        //views of the closed buffer move to the one taking its place
+        for view in &mut self.views {
+            if view.buffer == removed {
+                view.buffer = self.current_buffer;
+                view.cursor_position = Position::default();
+                view.offset = Position::default();
+            } else if view.buffer > removed {
+                view.buffer -= 1;
+            }
+        }
+        self.load_buffer();
+    }
+    //undoes the last edit, or redoes the last undone one with `redo`
+    fn undo(&mut self, redo: bool) {
+        self.selection_anchor = None;
+        let position = if redo {
+            self.document.redo()
+        } else {
+            self.document.undo()
+        };
+        if let Some(position) = position {
+            self.cursor_position = position;
+            self.scroll();
+        } else if redo {
+            self.status_message = StatusMessage::from("Nothing to redo".to_string());
+        } else {
+            self.status_message = StatusMessage::from("Nothing to undo".to_string());
+        }
+    }
+    //the selected range in document order, None when nothing is selected.
+    //the end is kept inside the document when the cursor is past the last row
+    fn selection(&self) -> Option<(Position, Position)> {
+        let anchor = self.selection_anchor?;
+        if anchor == self.cursor_position {
+            return None;
+        }
+        let clamp = |position: Position| {
+            if position.y < self.document.len() {
+                return position;
+            }
+            let y = self.document.len().saturating_sub(1);
+            Position { x: self.document.row(y).map_or(0, |row| row.len()), y }
+        };
+        let first = clamp(cmp::min(anchor, self.cursor_position));
+        let last = clamp(cmp::max(anchor, self.cursor_position));
+        Some((first, last))
+    }
+    //removes the selected text and puts the cursor where it was,
+    //returns whether there was anything to remove
+    fn delete_selection(&mut self) -> bool {
+        let selection = self.selection();
+        self.selection_anchor = None;
+        let Some((first, last)) = selection else {
+            return false;
+        };
+        self.document.delete_range(&first, &last);
+        self.cursor_position = first;
+        self.scroll();
+        true
+    }
+    //copies the selection into the clipboard, or the whole cursor line when
+    //nothing is selected. with `cut` the copied text is removed as well
+    fn copy(&mut self, cut: bool) {
+        if let Some((first, last)) = self.selection() {
+            self.clipboard.set(self.document.text_range(&first, &last), false);
+            if cut {
+                self.delete_selection();
+            }
+            return;
+        }
+        let y = self.cursor_position.y;
+        let Some(row) = self.document.row(y) else {
+            return;
+        };
+        self.clipboard.set(format!("{}\n", row.as_str()), true);
+        if !cut {
+            return;
+        }
+        //the last row has no line break after it, so the one before it goes instead
+        let (start, end) = if y + 1 < self.document.len() {
+            (Position { x: 0, y }, Position { x: 0, y: y + 1 })
+        } else if y > 0 {
+            let previous = y - 1;
+            let x = self.document.row(previous).map_or(0, |row| row.len());
+            (Position { x, y: previous }, Position { x: row.len(), y })
+        } else {
+            (Position { x: 0, y }, Position { x: row.len(), y })
+        };
+        self.document.delete_range(&start, &end);
+        self.cursor_position = Position { x: 0, y: cmp::min(y, self.document.len().saturating_sub(1)) };
+        self.scroll();
+    }
+    //inserts the clipboard at the cursor, replacing the selection if there is one.
+    //linewise text goes above the cursor line and the cursor stays on its line
+    fn paste(&mut self) {
+        let (text, linewise) = self.clipboard.contents();
+        if text.is_empty() {
+            self.status_message = StatusMessage::from("Nothing to paste".to_string());
+            return;
+        }
+        if self.delete_selection() || !linewise {
+            self.insert_pasted(&text);
+        } else if self.cursor_position.y < self.document.len() || self.document.is_empty() {
+            let end = self.document.insert_str(&Position { x: 0, y: self.cursor_position.y }, &text);
+            self.cursor_position.y = end.y;
+        } else {
+            //past the last row the lines are appended after it instead
+            let y = self.document.len() - 1;
+            let x = self.document.row(y).map_or(0, |row| row.len());
+            let text = format!("\n{}", text.trim_end_matches('\n'));
+            self.document.insert_str(&Position { x, y }, &text);
+        }
+        self.scroll();
+    }
+    //inserts a block of text at the cursor as one edit, terminals
+    //may send line breaks as \r so those are turned into \n first
+    fn insert_pasted(&mut self, text: &str) {
+        let text = text.replace("\r\n", "\n").replace('\r', "\n");
+        self.cursor_position = self.document.insert_str(&self.cursor_position, &text);
+        self.scroll();
+    }
+    //inserts a literal tab, or spaces up to the next tab stop when expanding tabs
+    fn insert_tab(&mut self) {
+        if self.config.expand_tabs {
+            let tab_width = self.config.tab_width;
+            for _ in 0..(tab_width - self.cursor_colum() % tab_width) {
+                self.document.insert(&self.cursor_position, ' ');
+                self.move_cursor(KeyCode::Right);
+            }
+        } else {
+            self.document.insert(&self.cursor_position, '\t');
+            self.move_cursor(KeyCode::Right);
+        }
+    }
     fn move_cursor(&mut self, key: KeyCode) {
-        let terminal_height = self.terminal.size().rows as usize;
+        let terminal_height = self.area().height;
         let Position { mut x, mut y } = self.cursor_position;
         let mut colums = if let Some(row) = self.document.row(y) {
             row.len()
@@ -252,17 +929,18 @@ impl Editor {
             0
         };
         let rows = self.document.len();
+        let colum = self.cursor_colum();
         //PageUp and PageDown will attempt to keep the same terminal cursor height
         //while moving by terminal_height amount of rows up or down
         //cmp::min() was used to limit cursor height range to document height through rows
         match key {
             KeyCode::Up => y = y.saturating_sub(1),
-            KeyCode::Down => if y < rows {y = y.saturating_add(1)},
+            KeyCode::Down if y < rows => y = y.saturating_add(1),
             KeyCode::Left => {
                 if x == 0 {
                      if y != y.saturating_sub(1) {
                         y = y.saturating_sub(1);
-                        if let Some(row) = self.document.row(y) {x = row.len()};
+                        if let Some(row) = self.document.row(y) {x = row.len()}
                      }
                 } else {
                     x = x.saturating_sub(1);
@@ -278,11 +956,11 @@ impl Editor {
                     x = x.saturating_add(1);
                 }
             },
-            KeyCode::PageUp => if y > 0 {
+            KeyCode::PageUp if y > 0 => {
                 y = y.saturating_sub(terminal_height);
                 self.offset.y = self.offset.y.saturating_sub(terminal_height);
             },
-            KeyCode::PageDown => if y < rows {
+            KeyCode::PageDown if y < rows => {
                 y = cmp::min(
                     rows.saturating_sub(terminal_height).saturating_add(y).saturating_sub(self.offset.y).saturating_add(1),
                      y.saturating_add(terminal_height)
@@ -296,6 +974,10 @@ impl Editor {
             KeyCode::End => x = colums,
             _ => (),
         }
+        //vertical movement keeps the screen colum rather than the grapheme index
+        if matches!(key, KeyCode::Up | KeyCode::Down | KeyCode::PageUp | KeyCode::PageDown) {
+            x = self.document.row(y).map_or(0, |row| row.index_at_colum(colum, self.config.tab_width));
+        }
         colums = if let Some(row) = self.document.row(y) {
             row.len()
         } else {
@@ -307,11 +989,20 @@ impl Editor {
         self.cursor_position = Position { x, y };
         self.scroll();
     }
+    //keeps the cursor on screen with the new size and clears whatever
+    //the terminal left behind so the next refresh redraws everything
+    fn resize(&mut self, colums: u16, rows: u16) {
+        self.terminal.resize(colums, rows);
+        self.update_layout();
+        Terminal::clear_screen();
+        self.scroll();
+    }
     fn scroll(&mut self) {
-        let Position { x, y } = self.cursor_position;
-        let colum = self.terminal.size().colums as usize;
-        let row = self.terminal.size().rows as usize;
-        let mut offset = &mut self.offset;
+        let y = self.cursor_position.y;
+        let x = self.cursor_colum();
+        let colum = self.text_width();
+        let row = self.area().height;
+        let offset = &mut self.offset;
         if y < offset.y {
             offset.y = y;
         } else if y >= offset.y.saturating_add(row) {
@@ -323,51 +1014,520 @@ impl Editor {
             offset.x = x.saturating_sub(colum).saturating_add(1);
         }
     }
-    fn promt(&mut self, promt: &str) -> Result<Option<String>, std::io::Error> {
+    //the callback runs after every key that edits the input or navigates,
+    //so callers can react while the user is still typing.
+    //returns None when the promt was cancelled with ESC
+    fn promt<C>(&mut self, promt: &str, mut callback: C) -> Result<Option<String>, std::io::Error>
+    where
+        C: FnMut(&mut Self, &KeyEvent, &String),
+    {
         let mut result = String::new();
         loop {
-            self.status_message = StatusMessage::from(format!("{}{}", promt, result));
+            self.status_message = StatusMessage::from(format!("{promt}{result}"));
             self.refresh_screen()?;
-            if let Event::Key(keyevent) = read()? {
+            let event = read()?;
+            if let Event::Resize(colums, rows) = event {
+                self.resize(colums, rows);
+            }
+            if let Event::Paste(text) = &event {
+                result.push_str(&text.replace(['\r', '\n'], " "));
+            }
+            if let Event::Key(keyevent) = event {
                 match keyevent.code {
                     KeyCode::Enter => {
                         break;
                     },
-                    KeyCode::Char(c) => {
-                        if !keyevent.modifiers.contains(KeyModifiers::CONTROL) {
-                            result.push(c);
-                        }
+                    KeyCode::Char(c) if !keyevent.modifiers.contains(KeyModifiers::CONTROL) => {
+                        result.push(c);
                     },
                     KeyCode::Backspace => {
-                        if !result.is_empty() {
-                            result.truncate(result.len() - 1);
-                        }
+                        result.pop();
                     },
                     KeyCode::Esc => {
-                        result.truncate(0);
-                        break;
+                        self.status_message = StatusMessage::from(String::new());
+                        return Ok(None);
                     }
                     _ => (),
                 }
+                callback(self, &keyevent, &result);
             }
         }
         self.status_message = StatusMessage::from(String::new());
-        if result.is_empty() {
-            return Ok(None);
-        }
         Ok(Some(result))
     }
+    //shows a question in the message bar and waits for a single character answer,
+    //returns None when ESC was pressed
+    fn confirm(&mut self, question: &str) -> Result<Option<char>, std::io::Error> {
+        self.status_message = StatusMessage::from(question.to_string());
+        loop {
+            self.refresh_screen()?;
+            let event = read()?;
+            if let Event::Resize(colums, rows) = event {
+                self.resize(colums, rows);
+            }
+            if let Event::Key(keyevent) = event {
+                match keyevent.code {
+                    KeyCode::Char(c) => return Ok(Some(c.to_ascii_lowercase())),
+                    KeyCode::Esc => return Ok(None),
+                    _ => (),
+                }
+            }
+        }
+    }
+    fn search(&mut self) {
+        self.finish_loading();
+        let old_position = self.cursor_position;
+        let old_offset = self.offset;
+        let mut direction = SearchDirection::Forward;
+        let query = self
+            .promt("Search (ESC to cancel, arrows to navigate): ", |editor, keyevent, query| {
+                let mut moved = false;
+                match keyevent.code {
+                    KeyCode::Right | KeyCode::Down => {
+                        direction = SearchDirection::Forward;
+                        editor.move_cursor(KeyCode::Right);
+                        moved = true;
+                    },
+                    KeyCode::Left | KeyCode::Up => direction = SearchDirection::Backward,
+                    _ => direction = SearchDirection::Forward,
+                }
+                if let Some(position) = editor.document.find(query, &editor.cursor_position, direction) {
+                    editor.cursor_position = position;
+                    editor.scroll();
+                } else if moved {
+                    editor.move_cursor(KeyCode::Left);
+                }
+                editor.highlighted_word = Some(query.clone());
+            })
+            .unwrap_or(None);
+        if query.is_none_or(|query| query.is_empty()) {
+            self.cursor_position = old_position;
+            self.offset = old_offset;
+            self.scroll();
+        }
+        self.highlighted_word = None;
+    }
+    //regex find and replace starting at the cursor and wrapping around once,
+    //matches are searched in the whole document so they may span line breaks
+    fn replace(&mut self) {
+        self.finish_loading();
+        let Some(pattern) = self.promt("Replace (regex): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        if pattern.is_empty() {
+            return;
+        }
+        let Ok(regex) = Regex::new(&pattern) else {
+            self.status_message = StatusMessage::from(format!("Invalid regex: {pattern}"));
+            return;
+        };
+        let Some(replacement) = self.promt("Replace with ($1 for groups): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        let start = self.document.offset_of(&self.cursor_position);
+        let mut matches = Matches::new(&regex, &replacement, self.document.text(), start);
+        let mut replace_all = false;
+        let mut count = 0;
+        while let Some((match_start, match_end, expanded)) = matches.next_match() {
+            let start_position = self.document.position_at(match_start);
+            let end_position = self.document.position_at(match_end);
+            if !replace_all {
+                self.cursor_position = start_position;
+                self.scroll();
+                let found = matches.text()[match_start..match_end].replace('\n', "\\n");
+                let question = format!("Replace \"{found}\"? (y)es (n)o (a)ll (q)uit");
+                match self.confirm(&question).unwrap_or(None) {
+                    Some('y') => (),
+                    Some('a') => replace_all = true,
+                    Some('n') => {
+                        matches.skip(match_start, match_end);
+                        continue;
+                    },
+                    _ => break,
+                }
+            }
+            self.cursor_position = self.document.replace(&start_position, &end_position, &expanded);
+            matches.replace(match_start, match_end, &expanded);
+            count += 1;
+        }
+        self.scroll();
+        self.status_message = StatusMessage::from(format!("Replaced {count} occurrence(s)"));
+    }
+    fn encoding_name(&self) -> String {
+        let bom = if self.document.has_bom() { " BOM" } else { "" };
+        format!("{}{bom}", self.document.encoding().name())
+    }
+    fn change_encoding(&mut self) {
+        let Some(name) = self.promt("Encoding (UTF-8, UTF-16LE, UTF-16BE or Latin-1, BOM after it for a byte order mark): ", |_, _, _| {}).unwrap_or(None) else {
+            return;
+        };
+        if let Some((encoding, bom)) = Encoding::from_name(&name) {
+            self.document.set_encoding(encoding, bom);
+            self.status_message = StatusMessage::from(format!("The file will be saved as {}", self.encoding_name()));
+        } else {
+            self.status_message = StatusMessage::from(format!("Unknown encoding {name}"));
+        }
+    }
+    //takes in what the background readers have read of every open file so far
+    fn load_documents(&mut self) {
+        let documents = iter::once(&mut self.document).chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        for document in documents {
+            let file_name = document.file.clone().unwrap_or_default();
+            if let Err(error) = document.load() {
+                self.status_message = StatusMessage::from(format!("Could not read all of {file_name}: {error}"));
+            }
+        }
+    }
+    //journals the unsaved changes of every open file, at most once per SWAP_INTERVAL.
+    //a document whose swap file could not be written is retried after its next edit
+    fn write_swap_files(&mut self) {
+        if self.last_swap.elapsed() < SWAP_INTERVAL {
+            return;
+        }
+        self.last_swap = Instant::now();
+        let documents = iter::once(&mut self.document).chain(self.buffers.iter_mut().map(|buffer| &mut buffer.document));
+        for document in documents {
+            if let Err(error) = document.write_swap() {
+                let file_name = document.file.clone().unwrap_or_default();
+                self.status_message = StatusMessage::from(format!("Could not write the swap file of {file_name}: {error}"));
+            }
+        }
+    }
+    //a swap file left next to an opened file means an earlier session ended
+    //with unsaved changes, each one found is offered for recovery. one that
+    //a running session still writes is only pointed out
+    fn recover_swap_files(&mut self) {
+        let current = self.current_buffer;
+        for index in 0..self.buffers.len() {
+            self.switch_buffer(index);
+            match self.document.read_swap() {
+                Ok(Some(Swap::Stale(text))) if !self.document.is_readonly() => {
+                    self.finish_loading();
+                    if text == self.document.text() {
+                        self.document.discard_swap();
+                    } else {
+                        self.offer_recovery(&text);
+                    }
+                },
+                Ok(Some(Swap::Live(owner))) => {
+                    let file_name = self.document.file.clone().unwrap_or_default();
+                    self.status_message = StatusMessage::from(format!(
+                        "{file_name} is being edited by hecto {owner} as well, changes here are not journaled"
+                    ));
+                },
+                Ok(_) => (),
+                Err(error) => {
+                    self.status_message = StatusMessage::from(format!("Could not read the swap file: {error}"));
+                },
+            }
+        }
+        self.switch_buffer(current);
+    }
+    //asks whether to take over the `swapped` text, a diff against the file is shown
+    //in place of the document on request. leaving with Esc keeps the file as it is and
+    //the swap file until the next edit replaces it
+    fn offer_recovery(&mut self, swapped: &str) {
+        let file_name = self.document.file.clone().unwrap_or_default();
+        let question = format!("{file_name} has unsaved changes from an earlier session: (r)ecover, (d)iff or (x) discard");
+        let mut put_aside = None;
+        let answer = loop {
+            let answer = self.confirm(&question).unwrap_or(None);
+            if answer != Some('d') {
+                break answer;
+            }
+            if put_aside.is_none() {
+                let mut diff = Document::default();
+                diff.insert_str(&Position::default(), &line_diff(&self.document.text(), swapped));
+                put_aside = Some(mem::replace(&mut self.document, diff));
+            }
+        };
+        if let Some(document) = put_aside {
+            self.document = document;
+        }
+        match answer {
+            Some('r') => {
+                let y = self.document.len().saturating_sub(1);
+                let end = Position { x: self.document.row(y).map_or(0, |row| row.len()), y };
+                self.document.replace(&Position::default(), &end, swapped);
+                self.document.checkpoint(&self.cursor_position, &Position::default());
+                self.cursor_position = Position::default();
+                self.status_message = StatusMessage::from(format!("Recovered the changes to {file_name}, save to keep them"));
+            },
+            Some('x') => {
+                self.document.discard_swap();
+                self.status_message = StatusMessage::from(format!("Discarded the changes to {file_name}"));
+            },
+            _ => self.status_message = StatusMessage::from(String::new()),
+        }
+    }
+    //notices when another program changed the file of the current buffer. an unmodified
+    //buffer is reloaded, otherwise the user is only told, as keys typed meanwhile
+    //must not pick a version for them
+    fn check_disk_changes(&mut self) {
+        if self.last_disk_check.elapsed() < DISK_CHECK_INTERVAL {
+            return;
+        }
+        self.last_disk_check = Instant::now();
+        if !self.document.check_disk() {
+            return;
+        }
+        let file_name = self.document.file.clone().unwrap_or_default();
+        if self.document.is_dirty() {
+            self.status_message = StatusMessage::from(format!(
+                "{file_name} changed on disk: Alt+R reloads it, Ctrl+S asks before overwriting it"
+            ));
+        } else {
+            self.reload();
+            self.status_message = StatusMessage::from(format!("Reloaded {file_name}, it changed on disk"));
+        }
+    }
+    //the Alt+R command, asks before unsaved changes are lost
+    fn ask_reload(&mut self) {
+        let Some(file_name) = self.document.file.clone() else {
+            self.status_message = StatusMessage::from("The buffer has no file to reload".to_string());
+            return;
+        };
+        if self.document.is_dirty() {
+            let question = format!("Reload {file_name} and lose your changes? (y/n)");
+            if self.confirm(&question).unwrap_or(None) != Some('y') {
+                self.status_message = StatusMessage::from(String::new());
+                return;
+            }
+        }
+        self.reload();
+    }
+    //replaces the current document with what is on disk now, unsaved changes are lost
+    fn reload(&mut self) {
+        let Some(file_name) = self.document.file.clone() else {
+            return;
+        };
+        match Document::open(&file_name) {
+            Ok(mut document) => {
+                document.set_readonly(self.document.is_readonly());
+                self.document.remove_swap();
+                self.document = document;
+                self.selection_anchor = None;
+                self.clamp_cursor();
+                self.scroll();
+                self.status_message = StatusMessage::from(format!("Reloaded {file_name}"));
+            },
+            Err(error) => {
+                self.status_message = StatusMessage::from(format!("Could not reload {file_name}: {error}"));
+            },
+        }
+    }
+    //search and replace need the whole file
+    fn finish_loading(&mut self) {
+        let file_name = self.document.file.clone().unwrap_or_default();
+        if let Err(error) = self.document.finish_loading() {
+            self.status_message = StatusMessage::from(format!("Could not read all of {file_name}: {error}"));
+        }
+    }
+    //saves the document, asking for a file name first with `ask_name`
+    fn save_as(&mut self, ask_name: bool) {
+        if ask_name {
+            let Some(file_name) = self.promt("Save as: ", |_, _, _| {}).unwrap_or(None).filter(|name| !name.is_empty()) else {
+                self.status_message = StatusMessage::from("Save aborted".to_string());
+                return;
+            };
+            if self.document.file.as_ref() != Some(&file_name) {
+                //the swap file and what was read from disk belong to the old name
+                self.document.remove_swap();
+                self.document.file = Some(file_name);
+                self.document.forget_disk();
+            }
+        }
+        if self.document.file.is_none() {
+            self.status_message = StatusMessage::from("Save aborted".to_string());
+        } else {
+            self.save();
+        }
+    }
+    //asks before writing over a file something else changed since it was read
     fn save(&mut self) {
-        if self.document.save().is_ok() {
-            self.status_message = 
-                StatusMessage::from("Saved successfully".to_string());
+        self.document.check_disk();
+        if self.document.is_changed_on_disk() {
+            let answer = self.confirm("The file changed on disk since it was read. Overwrite it? (y/n)").unwrap_or(None);
+            if answer != Some('y') {
+                self.status_message = StatusMessage::from("Save aborted".to_string());
+                return;
+            }
+        }
+        self.write_document();
+    }
+    fn write_document(&mut self) {
+        let file_name = self.document.file.clone().unwrap_or_default();
+        if let Err(error) = self.document.save(self.config.backup) {
+            self.status_message = StatusMessage::from(format!("Could not save {file_name}: {error}"));
         } else {
             self.status_message =
-                StatusMessage::from("Error occured while saving file".to_string());
+                StatusMessage::from("Saved successfully".to_string());
+        }
+    }
+}
+//the rows that differ between `old` and `new`, found between the rows both start
+//and end with, as one hunk of a unified diff
+fn line_diff(old: &str, new: &str) -> String {
+    let old: Vec<&str> = old.split('\n').collect();
+    let new: Vec<&str> = new.split('\n').collect();
+    let prefix = old.iter().zip(&new).take_while(|(old, new)| old == new).count();
+    let suffix = old[prefix..]
+        .iter()
+        .rev()
+        .zip(new[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    let removed = &old[prefix..old.len() - suffix];
+    let added = &new[prefix..new.len() - suffix];
+    let mut diff = format!("@@ -{},{} +{},{} @@", prefix + 1, removed.len(), prefix + 1, added.len());
+    for line in removed {
+        diff.push_str("\n-");
+        diff.push_str(line);
+    }
+    for line in added {
+        diff.push_str("\n+");
+        diff.push_str(line);
+    }
+    diff
+}
+//cuts `text` so it fits into `width` screen colums
+fn truncate_to_width(text: &mut String, width: usize) {
+    let mut used = 0;
+    let end = text[..]
+        .grapheme_indices(true)
+        .find(|(_, grapheme)| {
+            used += grapheme.width();
+            used > width
+        })
+        .map_or(text.len(), |(index, _)| index);
+    text.truncate(end);
+}
+//the matches of a regex from `start` to the end of the text and then, wrapping around
+//once, from its beginning up to `start`. the text is kept in step with the replacements
+//made, so later matches are found where they are in the document
+struct Matches<'a> {
+    regex: &'a Regex,
+    replacement: &'a str,
+    text: String,
+    start: usize,
+    at: usize,
+    //where the matches end after wrapping around
+    limit: usize,
+    wrapped: bool,
+}
+impl<'a> Matches<'a> {
+    fn new(regex: &'a Regex, replacement: &'a str, text: String, start: usize) -> Self {
+        let limit = text.len();
+        Self { regex, replacement, text, start, at: start, limit, wrapped: false }
+    }
+    fn text(&self) -> &str {
+        &self.text
+    }
+    //byte range of the next match and its expanded replacement. matches starting or
+    //ending inside a grapheme are passed over, the document cannot cut one apart
+    fn next_match(&mut self) -> Option<(usize, usize, String)> {
+        loop {
+            let found = if self.at > self.text.len() {
+                None
+            } else {
+                self.regex
+                    .captures_at(&self.text, self.at)
+                    .filter(|captures| !self.wrapped || captures.get(0).is_some_and(|m| m.start() < self.limit))
+            };
+            let Some(captures) = found else {
+                if self.wrapped || self.start == 0 {
+                    return None;
+                }
+                self.wrapped = true;
+                self.at = 0;
+                self.limit = self.start;
+                continue;
+            };
+            let whole = captures.get(0)?;
+            let (match_start, match_end) = (whole.start(), whole.end());
+            if !is_grapheme_boundary(&self.text, match_start) || !is_grapheme_boundary(&self.text, match_end) {
+                self.skip(match_start, match_end);
+                continue;
+            }
+            let mut expanded = String::new();
+            captures.expand(self.replacement, &mut expanded);
+            return Some((match_start, match_end, expanded));
         }
     }
+    //continues after a match that is left as it is
+    fn skip(&mut self, match_start: usize, match_end: usize) {
+        self.at = next_boundary(&self.text, match_start, match_end);
+    }
+    //continues after the text a match was replaced with, an empty match
+    //also passes over the character after it
+    fn replace(&mut self, match_start: usize, match_end: usize, expanded: &str) {
+        self.text.replace_range(match_start..match_end, expanded);
+        if self.wrapped {
+            self.limit = (self.limit + expanded.len()).saturating_sub(match_end - match_start);
+        }
+        let end = match_start + expanded.len();
+        self.at = if match_end == match_start {
+            next_boundary(&self.text, end, end)
+        } else {
+            end
+        };
+    }
+}
+fn is_grapheme_boundary(text: &str, offset: usize) -> bool {
+    GraphemeCursor::new(offset, text.len(), true).is_boundary(text, 0).unwrap_or(false)
+}
+//where to continue searching after a match, empty matches
+//skip one character so the same spot is not matched again
+fn next_boundary(text: &str, match_start: usize, match_end: usize) -> usize {
+    if match_end > match_start {
+        return match_end;
+    }
+    text[match_end..].chars().next().map_or(text.len() + 1, |c| match_end + c.len_utf8())
 }
-fn ded(error: std::io::Error) {
-    Terminal::clear_screen();
-    panic!("{}", error);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    //replaces every match the way Editor::replace does when answered with (a)ll
+    fn replace_all(text: &str, pattern: &str, replacement: &str, start: usize) -> (String, usize) {
+        let regex = Regex::new(pattern).unwrap();
+        let mut document = Document::default();
+        document.insert_str(&Position::default(), text);
+        let mut matches = Matches::new(&regex, replacement, document.text(), start);
+        let mut count = 0;
+        while let Some((match_start, match_end, expanded)) = matches.next_match() {
+            let start_position = document.position_at(match_start);
+            let end_position = document.position_at(match_end);
+            document.replace(&start_position, &end_position, &expanded);
+            matches.replace(match_start, match_end, &expanded);
+            assert_eq!(matches.text(), document.text());
+            count += 1;
+        }
+        (document.text(), count)
+    }
+    #[test]
+    fn deletes_adjacent_matches() {
+        assert_eq!(replace_all("xxab", "x", "", 0), ("ab".to_string(), 2));
+        assert_eq!(replace_all("aXbXc\nXX", "X", "--", 0), ("a--b--c\n----".to_string(), 4));
+    }
+    #[test]
+    fn wraps_around_once() {
+        assert_eq!(replace_all("x1 x2 x3", "x", "xx", 3), ("xx1 xx2 xx3".to_string(), 3));
+        assert_eq!(replace_all("x1\nx2", "x(\\d)", "$1$1", 3), ("11\n22".to_string(), 2));
+    }
+    #[test]
+    fn steps_over_empty_matches() {
+        assert_eq!(replace_all("ab", "", "-", 0), ("-a-b-".to_string(), 3));
+    }
+    #[test]
+    fn passes_over_matches_inside_graphemes() {
+        assert_eq!(replace_all("e\u{301}x", "\u{301}", "", 0), ("e\u{301}x".to_string(), 0));
+        assert_eq!(replace_all("e\u{301}xe\u{301}", "\u{301}|x", "", 0), ("e\u{301}e\u{301}".to_string(), 1));
+    }
+    #[test]
+    fn diffs_the_rows_that_changed() {
+        assert_eq!(line_diff("a\nb\nc\nd", "a\nB\nc\nd"), "@@ -2,1 +2,1 @@\n-b\n+B");
+        assert_eq!(line_diff("a\nd", "a\nb\nc\nd"), "@@ -2,0 +2,2 @@\n+b\n+c");
+        assert_eq!(line_diff("a\nb\nc", "a"), "@@ -2,2 +2,0 @@\n-b\n-c");
+        assert_eq!(line_diff("same", "same"), "@@ -2,0 +2,0 @@");
+    }
 }
@@ -0,0 +1,278 @@
+//bytes that are not valid UTF-8 are kept as the code points ESCAPE_BASE + byte,
+//at the end of the last private use plane, and written back as the raw byte.
+//valid text in that range is escaped byte by byte as well so it survives too
+const ESCAPE_BASE: u32 = 0x10_ff00;
+//bytes needed before zero bytes at every other position are taken as a sign of UTF-16
+const MIN_UTF16_SAMPLE: usize = 16;
+//how the bytes of a file map to text
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    //ISO-8859-1, every byte is the code point of the same value
+    Latin1,
+}
+impl Encoding {
+    //guesses the encoding from the first bytes of a file and tells whether they start
+    //with a byte order mark. the mark decides it, otherwise text with zero bytes at every
+    //other position is taken for UTF-16 if it decodes to text, and text that is not valid UTF-8 for Latin-1,
+    //unless it holds valid multi-byte sequences as well. such mostly UTF-8 text and
+    //binary data stay UTF-8, where the invalid bytes are escaped
+    pub fn detect(bytes: &[u8]) -> (Self, bool) {
+        for encoding in [Self::Utf8, Self::Utf16Le, Self::Utf16Be] {
+            if bytes.starts_with(encoding.bom()) {
+                return (encoding, true);
+            }
+        }
+        let units = bytes.len() / 2;
+        let zeros_at = |parity| bytes.iter().skip(parity).step_by(2).filter(|&&byte| byte == 0).count();
+        if bytes.len() >= MIN_UTF16_SAMPLE && bytes.len().is_multiple_of(2) {
+            let (even, odd) = (zeros_at(0), zeros_at(1));
+            if odd * 4 > units && even * 16 < units && Self::Utf16Le.is_utf16_text(bytes) {
+                return (Self::Utf16Le, false);
+            }
+            if even * 4 > units && odd * 16 < units && Self::Utf16Be.is_utf16_text(bytes) {
+                return (Self::Utf16Be, false);
+            }
+        }
+        match std::str::from_utf8(bytes) {
+            //a sequence cut off at the end of the sample still counts as valid
+            Err(error) if error.error_len().is_some() && !is_binary(bytes) && !has_multibyte(bytes) => {
+                (Self::Latin1, false)
+            },
+            _ => (Self::Utf8, false),
+        }
+    }
+    //parses names like those shown in the status bar, a trailing BOM asks for a byte order mark
+    pub fn from_name(name: &str) -> Option<(Self, bool)> {
+        let name: String = name
+            .chars()
+            .filter(char::is_ascii_alphanumeric)
+            .map(|c| c.to_ascii_lowercase())
+            .collect();
+        let (name, bom) = match name.strip_suffix("bom") {
+            Some(name) => (name, true),
+            None => (&name[..], false),
+        };
+        let encoding = match name {
+            "utf8" => Self::Utf8,
+            "utf16le" => Self::Utf16Le,
+            "utf16be" => Self::Utf16Be,
+            "latin1" | "iso88591" => Self::Latin1,
+            _ => return None,
+        };
+        if bom && encoding.bom().is_empty() {
+            return None;
+        }
+        Some((encoding, bom))
+    }
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Utf8 => "UTF-8",
+            Self::Utf16Le => "UTF-16LE",
+            Self::Utf16Be => "UTF-16BE",
+            Self::Latin1 => "Latin-1",
+        }
+    }
+    //byte order mark, Latin-1 has none
+    pub fn bom(self) -> &'static [u8] {
+        match self {
+            Self::Utf8 => b"\xef\xbb\xbf",
+            Self::Utf16Le => b"\xff\xfe",
+            Self::Utf16Be => b"\xfe\xff",
+            Self::Latin1 => b"",
+        }
+    }
+    //decodes bytes that do not include the byte order mark. invalid UTF-8, unpaired
+    //UTF-16 surrogates and a lone byte at the end of UTF-16 are escaped
+    pub fn decode(self, bytes: &[u8]) -> String {
+        match self {
+            Self::Utf8 => {
+                let mut text = String::with_capacity(bytes.len());
+                for chunk in bytes.utf8_chunks() {
+                    for c in chunk.valid().chars() {
+                        if escaped_byte(c).is_some() {
+                            text.extend(c.encode_utf8(&mut [0; 4]).bytes().map(escape));
+                        } else {
+                            text.push(c);
+                        }
+                    }
+                    text.extend(chunk.invalid().iter().copied().map(escape));
+                }
+                text
+            },
+            Self::Latin1 => bytes.iter().map(|&byte| char::from(byte)).collect(),
+            Self::Utf16Le | Self::Utf16Be => {
+                let mut text = String::with_capacity(bytes.len() / 2);
+                for c in char::decode_utf16(self.units(bytes)) {
+                    match c {
+                        Ok(c) if escaped_byte(c).is_none() => text.push(c),
+                        Ok(c) => text.extend(c.encode_utf16(&mut [0; 2]).iter().flat_map(|&unit| self.unit_bytes(unit)).map(escape)),
+                        Err(error) => text.extend(self.unit_bytes(error.unpaired_surrogate()).into_iter().map(escape)),
+                    }
+                }
+                if !bytes.len().is_multiple_of(2) {
+                    text.extend(bytes.last().copied().map(escape));
+                }
+                text
+            },
+        }
+    }
+    //the UTF-16 code units in `bytes`, a lone byte at the end is left out
+    fn units(self, bytes: &[u8]) -> impl Iterator<Item = u16> + '_ {
+        bytes.chunks_exact(2).map(move |pair| match self {
+            Self::Utf16Le => u16::from_le_bytes([pair[0], pair[1]]),
+            _ => u16::from_be_bytes([pair[0], pair[1]]),
+        })
+    }
+    fn unit_bytes(self, unit: u16) -> [u8; 2] {
+        match self {
+            Self::Utf16Le => unit.to_le_bytes(),
+            _ => unit.to_be_bytes(),
+        }
+    }
+    //whether `bytes` decode to text without unpaired surrogates or control characters.
+    //a surrogate pair cut off at the end of the sample still counts
+    fn is_utf16_text(self, bytes: &[u8]) -> bool {
+        let mut units: Vec<u16> = self.units(bytes).collect();
+        if units.last().is_some_and(|unit| (0xd800..0xdc00).contains(unit)) {
+            units.pop();
+        }
+        char::decode_utf16(units).all(|c| c.is_ok_and(|c| !c.is_control() || matches!(c, '\t' | '\n' | '\r' | '\x0c' | '\x1b')))
+    }
+    //only Latin-1 cannot hold every character
+    pub fn can_encode(self, text: &str) -> bool {
+        self != Self::Latin1 || text.chars().all(|c| u32::from(c) <= 0xff || escaped_byte(c).is_some())
+    }
+    //escaped bytes are written as they are whatever the encoding
+    pub fn encode(self, text: &str) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(text.len());
+        for c in text.chars() {
+            if let Some(byte) = escaped_byte(c) {
+                bytes.push(byte);
+                continue;
+            }
+            match self {
+                Self::Utf8 => bytes.extend_from_slice(c.encode_utf8(&mut [0; 4]).as_bytes()),
+                Self::Latin1 => bytes.push(u8::try_from(c).unwrap_or(b'?')),
+                Self::Utf16Le => bytes.extend(c.encode_utf16(&mut [0; 2]).iter().flat_map(|unit| unit.to_le_bytes())),
+                Self::Utf16Be => bytes.extend(c.encode_utf16(&mut [0; 2]).iter().flat_map(|unit| unit.to_be_bytes())),
+            }
+        }
+        bytes
+    }
+}
+//control bytes other than whitespace and escape sequences do not appear in text
+fn is_binary(bytes: &[u8]) -> bool {
+    bytes
+        .iter()
+        .any(|&byte| byte < 0x20 && !matches!(byte, b'\t' | b'\n' | b'\r' | 0x0c | 0x1b))
+}
+//whether valid UTF-8 sequences of more than one byte appear among invalid ones
+fn has_multibyte(bytes: &[u8]) -> bool {
+    bytes.utf8_chunks().any(|chunk| !chunk.valid().is_ascii())
+}
+fn escape(byte: u8) -> char {
+    char::from_u32(ESCAPE_BASE + u32::from(byte)).unwrap_or(char::REPLACEMENT_CHARACTER)
+}
+//the raw byte `c` stands for, if it is an escaped one
+pub fn escaped_byte(c: char) -> Option<u8> {
+    u32::from(c).checked_sub(ESCAPE_BASE).and_then(|byte| u8::try_from(byte).ok())
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    //what saving the decoded bytes writes back
+    fn round_trip(bytes: &[u8]) -> Vec<u8> {
+        let (encoding, bom) = Encoding::detect(bytes);
+        let start = if bom { encoding.bom().len() } else { 0 };
+        let mut saved = bytes[..start].to_vec();
+        saved.extend(encoding.encode(&encoding.decode(&bytes[start..])));
+        saved
+    }
+    #[test]
+    fn takes_text_that_is_not_utf8_for_latin1() {
+        assert_eq!(Encoding::detect(b"caf\xe9 cr\xe8me"), (Encoding::Latin1, false));
+        assert_eq!(Encoding::Latin1.decode(b"caf\xe9"), "café");
+        assert_eq!(round_trip(b"caf\xe9 cr\xe8me\n"), b"caf\xe9 cr\xe8me\n");
+    }
+    #[test]
+    fn escapes_a_stray_byte_in_mostly_utf8_text() {
+        let bytes = "café \u{2014} ".bytes().chain([0xff]).chain("naïve\n".bytes()).collect::<Vec<u8>>();
+        assert_eq!(Encoding::detect(&bytes), (Encoding::Utf8, false));
+        assert_eq!(Encoding::Utf8.decode(&bytes), "café \u{2014} \u{10ffff}naïve\n");
+        assert_eq!(round_trip(&bytes), bytes);
+    }
+    fn utf16le(text: &str) -> Vec<u8> {
+        text.encode_utf16().flat_map(u16::to_le_bytes).collect()
+    }
+    #[test]
+    fn detects_utf16_without_bom() {
+        let bytes = utf16le("some text in UTF-16\n");
+        assert_eq!(Encoding::detect(&bytes), (Encoding::Utf16Le, false));
+        let bytes: Vec<u8> = "some text in UTF-16\n".encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(Encoding::detect(&bytes), (Encoding::Utf16Be, false));
+        assert_eq!(Encoding::Utf16Be.decode(&bytes), "some text in UTF-16\n");
+    }
+    #[test]
+    fn keeps_binary_data_byte_for_byte() {
+        let bytes = b"bin\x00\xff\xfe\n";
+        assert_eq!(Encoding::detect(bytes), (Encoding::Utf8, false));
+        assert_eq!(round_trip(bytes), bytes);
+        let numbers: Vec<u8> = (0..512u16).flat_map(u16::to_le_bytes).collect();
+        assert_eq!(Encoding::detect(&numbers).0, Encoding::Utf8);
+        assert_eq!(round_trip(&numbers), numbers);
+        let mut seed = 0x2545_f491_u32;
+        let noise: Vec<u8> = (0..4096)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed.to_le_bytes()[0]
+            })
+            .collect();
+        assert_eq!(round_trip(&noise), noise);
+    }
+    #[test]
+    fn escapes_what_utf16_cannot_decode() {
+        let mut bytes = b"\xff\xfe".to_vec();
+        bytes.extend(utf16le("ab"));
+        bytes.extend([0x00, 0xd8]);
+        bytes.extend(utf16le("c\n"));
+        bytes.push(0x41);
+        let text = Encoding::Utf16Le.decode(&bytes[2..]);
+        assert_eq!(text, "ab\u{10ff00}\u{10ffd8}c\n\u{10ff41}");
+        assert_eq!(round_trip(&bytes), bytes);
+    }
+    #[test]
+    fn escapes_characters_in_the_escape_range() {
+        let mut bytes = b"\xfe\xff".to_vec();
+        bytes.extend("a\u{10ff41}".encode_utf16().flat_map(u16::to_be_bytes));
+        assert_eq!(round_trip(&bytes), bytes);
+        let bytes = "a\u{10ff41}b".as_bytes();
+        assert_eq!(Encoding::Utf8.decode(bytes).chars().count(), 6);
+        assert_eq!(round_trip(bytes), bytes);
+    }
+    #[test]
+    fn detects_byte_order_marks() {
+        assert_eq!(Encoding::detect(b"\xef\xbb\xbfa"), (Encoding::Utf8, true));
+        assert_eq!(Encoding::detect(b"\xff\xfea\x00"), (Encoding::Utf16Le, true));
+        assert_eq!(Encoding::detect(b"\xfe\xff\x00a"), (Encoding::Utf16Be, true));
+    }
+    #[test]
+    fn parses_names() {
+        assert_eq!(Encoding::from_name("utf-16le"), Some((Encoding::Utf16Le, false)));
+        assert_eq!(Encoding::from_name("UTF-8 BOM"), Some((Encoding::Utf8, true)));
+        assert_eq!(Encoding::from_name("iso-8859-1"), Some((Encoding::Latin1, false)));
+        assert_eq!(Encoding::from_name("latin1 bom"), None);
+        assert_eq!(Encoding::from_name("ebcdic"), None);
+    }
+    #[test]
+    fn refuses_to_encode_what_latin1_cannot_hold() {
+        assert!(Encoding::Latin1.can_encode("café"));
+        assert!(!Encoding::Latin1.can_encode("\u{2014}"));
+        assert!(Encoding::Utf16Be.can_encode("\u{2014}"));
+    }
+}
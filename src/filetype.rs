@@ -0,0 +1,104 @@
+pub struct FileType {
+    name: String,
+    hl_opts: HighlightingOptions,
+}
+#[derive(Default)]
+#[allow(clippy::struct_excessive_bools)]
+pub struct HighlightingOptions {
+    numbers: bool,
+    strings: bool,
+    characters: bool,
+    comments: bool,
+    multiline_comments: bool,
+    primary_keywords: Vec<String>,
+    secondary_keywords: Vec<String>,
+}
+impl Default for FileType {
+    fn default() -> Self {
+        Self {
+            name: String::from("No filetype"),
+            hl_opts: HighlightingOptions::default(),
+        }
+    }
+}
+impl FileType {
+    pub fn name(&self) -> String {
+        self.name.clone()
+    }
+    pub fn highlighting_options(&self) -> &HighlightingOptions {
+        &self.hl_opts
+    }
+    //picks the definition by the extension of the file name
+    pub fn from(file_name: &str) -> Self {
+        let extension = file_name.rsplit_once('.').map_or("", |(_, extension)| extension);
+        match extension {
+            "rs" => Self {
+                name: String::from("Rust"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    characters: true,
+                    comments: true,
+                    multiline_comments: true,
+                    primary_keywords: words(&[
+                        "as", "break", "const", "continue", "crate", "else", "enum", "extern", "false",
+                        "fn", "for", "if", "impl", "in", "let", "loop", "match", "mod", "move", "mut",
+                        "pub", "ref", "return", "self", "Self", "static", "struct", "super", "trait",
+                        "true", "type", "unsafe", "use", "where", "while", "dyn", "async", "await",
+                    ]),
+                    secondary_keywords: words(&[
+                        "bool", "char", "i8", "i16", "i32", "i64", "i128", "isize", "u8", "u16", "u32",
+                        "u64", "u128", "usize", "f32", "f64", "str", "String", "Vec", "Option", "Result",
+                        "Box", "Some", "None", "Ok", "Err",
+                    ]),
+                },
+            },
+            "c" | "h" | "cpp" | "hpp" | "cc" => Self {
+                name: String::from("C"),
+                hl_opts: HighlightingOptions {
+                    numbers: true,
+                    strings: true,
+                    characters: true,
+                    comments: true,
+                    multiline_comments: true,
+                    primary_keywords: words(&[
+                        "switch", "if", "while", "for", "break", "continue", "return", "else", "struct",
+                        "union", "typedef", "static", "enum", "class", "case", "default", "do", "goto",
+                        "sizeof", "const", "extern", "volatile", "#include", "#define",
+                    ]),
+                    secondary_keywords: words(&[
+                        "int", "long", "double", "float", "char", "unsigned", "signed", "void", "short",
+                        "size_t", "bool",
+                    ]),
+                },
+            },
+            _ => Self::default(),
+        }
+    }
+}
+impl HighlightingOptions {
+    pub fn numbers(&self) -> bool {
+        self.numbers
+    }
+    pub fn strings(&self) -> bool {
+        self.strings
+    }
+    pub fn characters(&self) -> bool {
+        self.characters
+    }
+    pub fn comments(&self) -> bool {
+        self.comments
+    }
+    pub fn multiline_comments(&self) -> bool {
+        self.multiline_comments
+    }
+    pub fn primary_keywords(&self) -> &Vec<String> {
+        &self.primary_keywords
+    }
+    pub fn secondary_keywords(&self) -> &Vec<String> {
+        &self.secondary_keywords
+    }
+}
+fn words(list: &[&str]) -> Vec<String> {
+    list.iter().map(|word| (*word).to_string()).collect()
+}
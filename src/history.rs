@@ -0,0 +1,139 @@
+use crate::Position;
+use std::mem;
+use unicode_segmentation::UnicodeSegmentation;
+pub enum Edit {
+    Insert { at: Position, text: String },
+    Delete { at: Position, text: String },
+    AppendRow,
+}
+impl Edit {
+    //a single typed grapheme, blocks of text and line breaks start steps of their own
+    fn is_typing(&self) -> bool {
+        match self {
+            Edit::Insert { text, .. } => !text.contains('\n') && text.graphemes(true).count() == 1,
+            Edit::Delete { .. } => false,
+            Edit::AppendRow => true,
+        }
+    }
+}
+pub struct Step {
+    pub edits: Vec<Edit>,
+    pub cursor_before: Position,
+    pub cursor_after: Position,
+    //whether later typing may still be merged into this step
+    typing: bool,
+}
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Step>,
+    redo: Vec<Step>,
+    pending: Vec<Edit>,
+    //whether the pending edits are to stay a step of their own
+    apart: bool,
+}
+impl History {
+    pub fn record(&mut self, edit: Edit) {
+        self.pending.push(edit);
+    }
+    //keeps the pending edits from merging with the typing before or after them
+    pub fn keep_apart(&mut self) {
+        self.apart = true;
+    }
+    //every edit made while handling one event becomes one step,
+    //consecutive typed characters are merged into the previous step
+    //as long as the cursor did not jump in between
+    pub fn commit(&mut self, cursor_before: Position, cursor_after: Position) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let edits = mem::take(&mut self.pending);
+        let typing = !mem::take(&mut self.apart) && edits.iter().all(Edit::is_typing);
+        self.redo.clear();
+        if typing {
+            if let Some(last) = self.undo.last_mut() {
+                if last.cursor_after == cursor_before && last.typing {
+                    last.edits.extend(edits);
+                    last.cursor_after = cursor_after;
+                    return;
+                }
+            }
+        }
+        self.undo.push(Step { edits, cursor_before, cursor_after, typing });
+    }
+    pub fn pop_undo(&mut self) -> Option<Step> {
+        self.undo.pop()
+    }
+    pub fn push_undo(&mut self, step: Step) {
+        self.undo.push(step);
+    }
+    pub fn pop_redo(&mut self) -> Option<Step> {
+        self.redo.pop()
+    }
+    pub fn push_redo(&mut self, step: Step) {
+        self.redo.push(step);
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    fn insert(history: &mut History, x: usize, text: &str) {
+        history.record(Edit::Insert { at: Position { x, y: 0 }, text: text.to_string() });
+        history.commit(Position { x, y: 0 }, Position { x: x + text.len(), y: 0 });
+    }
+    fn steps(history: &mut History) -> Vec<usize> {
+        let mut steps = Vec::new();
+        while let Some(step) = history.pop_undo() {
+            steps.push(step.edits.len());
+        }
+        steps
+    }
+    #[test]
+    fn merges_typed_characters() {
+        let mut history = History::default();
+        insert(&mut history, 0, "a");
+        insert(&mut history, 1, "b");
+        insert(&mut history, 2, "c");
+        assert_eq!(steps(&mut history), vec![3]);
+    }
+    #[test]
+    fn does_not_merge_after_the_cursor_jumped() {
+        let mut history = History::default();
+        insert(&mut history, 0, "a");
+        insert(&mut history, 5, "b");
+        assert_eq!(steps(&mut history), vec![1, 1]);
+    }
+    #[test]
+    fn keeps_blocks_of_text_apart_from_typing() {
+        let mut history = History::default();
+        insert(&mut history, 0, "a");
+        insert(&mut history, 1, "b");
+        insert(&mut history, 2, "PASTED");
+        insert(&mut history, 8, "LINE\nTWO");
+        assert_eq!(steps(&mut history), vec![1, 1, 2]);
+    }
+    #[test]
+    fn keeps_steps_apart_on_request() {
+        let mut history = History::default();
+        insert(&mut history, 0, "a");
+        history.keep_apart();
+        insert(&mut history, 1, "b");
+        insert(&mut history, 2, "c");
+        assert_eq!(steps(&mut history), vec![1, 1, 1]);
+    }
+    #[test]
+    fn line_breaks_start_a_step() {
+        let mut history = History::default();
+        insert(&mut history, 0, "a");
+        insert(&mut history, 1, "\n");
+        assert_eq!(steps(&mut history), vec![1, 1]);
+    }
+    #[test]
+    fn new_edits_clear_redo() {
+        let mut history = History::default();
+        insert(&mut history, 0, "a");
+        let step = history.pop_undo().unwrap();
+        history.push_redo(step);
+        insert(&mut history, 0, "b");
+        assert!(history.pop_redo().is_none());
+    }
+}
@@ -0,0 +1,161 @@
+use std::mem;
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Rect {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize,
+}
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SplitDirection {
+    //one view above the other
+    Horizontal,
+    //views side by side
+    Vertical,
+}
+//how the text area is divided between views, leaves hold view indices
+pub enum Layout {
+    View(usize),
+    Split {
+        direction: SplitDirection,
+        first: Box<Layout>,
+        second: Box<Layout>,
+    },
+}
+impl Layout {
+    //collects the area of every view inside `rect` together with the
+    //one colum or row wide separators drawn between split views
+    pub fn areas(&self, rect: Rect, views: &mut Vec<(usize, Rect)>, separators: &mut Vec<(SplitDirection, Rect)>) {
+        match self {
+            Layout::View(view) => views.push((*view, rect)),
+            Layout::Split { direction, first, second } => {
+                let (first_rect, separator, second_rect) = match direction {
+                    SplitDirection::Horizontal => {
+                        let height = rect.height.saturating_sub(1) / 2;
+                        (
+                            Rect { height, ..rect },
+                            Rect { y: rect.y + height, height: 1, ..rect },
+                            Rect { y: rect.y + height + 1, height: rect.height.saturating_sub(height + 1), ..rect },
+                        )
+                    },
+                    SplitDirection::Vertical => {
+                        let width = rect.width.saturating_sub(1) / 2;
+                        (
+                            Rect { width, ..rect },
+                            Rect { x: rect.x + width, width: 1, ..rect },
+                            Rect { x: rect.x + width + 1, width: rect.width.saturating_sub(width + 1), ..rect },
+                        )
+                    },
+                };
+                first.areas(first_rect, views, separators);
+                separators.push((*direction, separator));
+                second.areas(second_rect, views, separators);
+            },
+        }
+    }
+    //view indices in the order they appear on screen
+    pub fn views(&self) -> Vec<usize> {
+        match self {
+            Layout::View(view) => vec![*view],
+            Layout::Split { first, second, .. } => {
+                let mut views = first.views();
+                views.extend(second.views());
+                views
+            },
+        }
+    }
+    //replaces the leaf of `view` with a split between it and `new_view`
+    pub fn split(&mut self, view: usize, new_view: usize, direction: SplitDirection) -> bool {
+        match self {
+            Layout::View(index) if *index == view => {
+                *self = Layout::Split {
+                    direction,
+                    first: Box::new(Layout::View(view)),
+                    second: Box::new(Layout::View(new_view)),
+                };
+                true
+            },
+            Layout::View(_) => false,
+            Layout::Split { first, second, .. } => {
+                first.split(view, new_view, direction) || second.split(view, new_view, direction)
+            },
+        }
+    }
+    //removes the leaf of `view` so its sibling takes the space, views
+    //after it are renumbered to match their new place in the list
+    pub fn remove(&mut self, view: usize) {
+        self.collapse(view);
+        self.renumber(view);
+    }
+    fn collapse(&mut self, view: usize) -> bool {
+        let Layout::Split { first, second, .. } = self else {
+            return false;
+        };
+        let sibling = if matches!(**first, Layout::View(index) if index == view) {
+            mem::replace(second, Box::new(Layout::View(0)))
+        } else if matches!(**second, Layout::View(index) if index == view) {
+            mem::replace(first, Box::new(Layout::View(0)))
+        } else {
+            return first.collapse(view) || second.collapse(view);
+        };
+        *self = *sibling;
+        true
+    }
+    fn renumber(&mut self, removed: usize) {
+        match self {
+            Layout::View(index) => {
+                if *index > removed {
+                    *index -= 1;
+                }
+            },
+            Layout::Split { first, second, .. } => {
+                first.renumber(removed);
+                second.renumber(removed);
+            },
+        }
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    type Areas = (Vec<(usize, Rect)>, Vec<(SplitDirection, Rect)>);
+    fn areas(layout: &Layout, rect: Rect) -> Areas {
+        let mut views = Vec::new();
+        let mut separators = Vec::new();
+        layout.areas(rect, &mut views, &mut separators);
+        (views, separators)
+    }
+    const SCREEN: Rect = Rect { x: 0, y: 0, width: 81, height: 21 };
+    #[test]
+    fn splits_the_area_around_a_separator() {
+        let mut layout = Layout::View(0);
+        assert!(layout.split(0, 1, SplitDirection::Vertical));
+        let (views, separators) = areas(&layout, SCREEN);
+        assert_eq!(views, [
+            (0, Rect { x: 0, y: 0, width: 40, height: 21 }),
+            (1, Rect { x: 41, y: 0, width: 40, height: 21 }),
+        ]);
+        assert_eq!(separators, [(SplitDirection::Vertical, Rect { x: 40, y: 0, width: 1, height: 21 })]);
+        assert!(layout.split(1, 2, SplitDirection::Horizontal));
+        let (views, _) = areas(&layout, SCREEN);
+        assert_eq!(views[1..], [
+            (1, Rect { x: 41, y: 0, width: 40, height: 10 }),
+            (2, Rect { x: 41, y: 11, width: 40, height: 10 }),
+        ]);
+        assert!(!layout.split(5, 6, SplitDirection::Horizontal));
+        assert_eq!(layout.views(), [0, 1, 2]);
+    }
+    #[test]
+    fn gives_the_space_of_a_removed_view_to_its_sibling() {
+        let mut layout = Layout::View(0);
+        layout.split(0, 1, SplitDirection::Vertical);
+        layout.split(1, 2, SplitDirection::Horizontal);
+        layout.remove(1);
+        assert_eq!(layout.views(), [0, 1]);
+        let (views, _) = areas(&layout, SCREEN);
+        assert_eq!(views[1], (1, Rect { x: 41, y: 0, width: 40, height: 21 }));
+        layout.remove(0);
+        assert_eq!(layout.views(), [0]);
+        assert_eq!(areas(&layout, SCREEN).0, [(0, SCREEN)]);
+    }
+}
@@ -1,14 +1,51 @@
 #![warn(clippy::all, clippy::pedantic)]
+#![allow(clippy::missing_errors_doc, clippy::missing_panics_doc, clippy::must_use_candidate)]
+use std::env;
+use std::process;
+mod args;
 mod terminal;
 mod editor;
 mod document;
 mod row;
+mod history;
+mod highlighting;
+mod filetype;
+mod config;
+mod clipboard;
+mod layout;
+mod encoding;
 pub use terminal::Terminal;
 use editor::Editor;
+use args::Command;
 pub use editor::Position;
+pub use editor::SearchDirection;
 pub use document::Document;
+pub use document::LineEnding;
+pub use encoding::Encoding;
 pub use row::Row;
+pub use filetype::FileType;
+pub use config::Config;
 
+//exits with 2 when the arguments make no sense and with 1 when editing fails,
+//both reported before the terminal is taken over or after it is given back
 fn main() {
-    Editor::default().run().unwrap();
+    let args = match args::parse(env::args().skip(1)) {
+        Ok(Command::Edit(args)) => args,
+        Ok(Command::Help) => {
+            println!("{}", args::USAGE);
+            return;
+        },
+        Ok(Command::Version) => {
+            println!("hecto {}", env!("CARGO_PKG_VERSION"));
+            return;
+        },
+        Err(error) => {
+            eprintln!("hecto: {error}\nTry 'hecto --help' for more information.");
+            process::exit(2);
+        },
+    };
+    if let Err(error) = Editor::new(&args).and_then(|mut editor| editor.run()) {
+        eprintln!("hecto: {error}");
+        process::exit(1);
+    }
 }
\ No newline at end of file
@@ -1,77 +1,331 @@
 use std::cmp;
+use std::borrow::Cow;
+use std::ops::Range;
 use unicode_segmentation::UnicodeSegmentation;
-#[derive(Default)]
+use unicode_width::UnicodeWidthStr;
+use crate::SearchDirection;
+use crate::highlighting;
+use crate::filetype::HighlightingOptions;
+use crate::encoding::escaped_byte;
+//one line of a document, taken out of its text to be measured and drawn
+#[derive(Default, Clone)]
 pub struct Row {
     string: String,
-    len: usize,
+    //byte offset and screen width of every grapheme, so lengths, index conversions
+    //and colums need no rescan. tabs have no width of their own, it depends on
+    //the colum they are drawn at
+    boundaries: Vec<usize>,
+    widths: Vec<u8>,
+    highlighting: Vec<highlighting::Type>,
 }
 impl From<&str> for Row {
     fn from(slice: &str) -> Self {
-        let mut row = Self { 
+        let (boundaries, widths) = slice
+            .grapheme_indices(true)
+            .map(|(index, grapheme)| (index, width(grapheme)))
+            .unzip();
+        Self {
             string: String::from(slice),
-            len: 0,
-        };
-        row.update_len();
-        row
+            boundaries,
+            widths,
+            highlighting: Vec::new(),
+        }
     }
 }
 impl Row {
-    pub fn render(&self, start: usize, end: usize) -> String {
-        let end = cmp::min(end, self.string.len());
-        let start = cmp::min(start, end);
-        let mut result = String::new();
-        for grapheme in self.string[..]
-            .graphemes(true)
-            .skip(start)
-            .take(end - start)
-            {
-                result.push_str(grapheme);
+    //renders what lies between screen colums `start` and `end`, grouped into runs of
+    //the same highlight class and of whether the graphemes fall in `selected`.
+    //wide graphemes cut by either edge become spaces and tabs are expanded
+    //up to the next multiple of `tab_width`
+    pub fn render(&self, start: usize, end: usize, tab_width: usize, selected: &Range<usize>) -> Vec<(highlighting::Type, bool, String)> {
+        let mut result: Vec<(highlighting::Type, bool, String)> = Vec::new();
+        let mut colum = 0;
+        for (index, grapheme) in self.graphemes().enumerate() {
+            if colum >= end {
+                break;
+            }
+            let width = self.width_at(index, colum, tab_width);
+            let next = colum + width;
+            if next > start {
+                let text = if colum < start || next > end {
+                    " ".repeat(cmp::min(next, end) - cmp::max(colum, start))
+                } else {
+                    glyph(grapheme, width).into_owned()
+                };
+                let hl_type = self.highlight_at(index);
+                let is_selected = selected.contains(&index);
+                match result.last_mut() {
+                    Some((last_type, last_selected, run)) if *last_type == hl_type && *last_selected == is_selected => {
+                        run.push_str(&text);
+                    },
+                    _ => result.push((hl_type, is_selected, text)),
+                }
             }
+            colum = next;
+        }
         result
     }
+    //translates a grapheme index into the screen colum it is drawn at
+    pub fn width_until(&self, at: usize, tab_width: usize) -> usize {
+        (0..cmp::min(at, self.len())).fold(0, |colum, index| colum + self.width_at(index, colum, tab_width))
+    }
+    //translates a screen colum back into the index of the grapheme drawn there
+    pub fn index_at_colum(&self, colum: usize, tab_width: usize) -> usize {
+        let mut current = 0;
+        for index in 0..self.len() {
+            current += self.width_at(index, current, tab_width);
+            if current > colum {
+                return index;
+            }
+        }
+        self.len()
+    }
     pub fn len(&self) -> usize {
-        self.string[..].graphemes(true).count()
+        self.boundaries.len()
+    }
+    //the graphemes of the row, cut at the cached boundaries
+    fn graphemes(&self) -> impl Iterator<Item = &str> + '_ {
+        self.boundaries
+            .iter()
+            .enumerate()
+            .map(|(index, &start)| &self.string[start..self.byte_index(index + 1)])
+    }
+    //how many colums the grapheme at `index` takes when drawn at `colum`,
+    //tabs reach up to the next multiple of `tab_width`
+    fn width_at(&self, index: usize, colum: usize, tab_width: usize) -> usize {
+        match self.widths[index] {
+            0 => tab_width - colum % tab_width,
+            width => usize::from(width),
+        }
     }
     pub fn is_empty(&self) -> bool {
         self.string.is_empty()
     }
-    pub fn insert(&mut self, at: usize, c:char) {
-        if at >= self.len() {
-            self.string.push(c);
-        } else {
-            let mut result: String = self.string[..].graphemes(true).take(at).collect();
-            let remainder: String = self.string[..].graphemes(true).skip(at).collect();
-            result.push(c);
-            result.push_str(&remainder);
-            self.string = result;
+    //returns the grapheme index of the first match at or after `at` going forward,
+    //or of the last match starting before `at` going backward, which may run past it
+    pub fn find(&self, query: &str, at: usize, direction: SearchDirection) -> Option<usize> {
+        if at > self.len() || query.is_empty() {
+            return None;
         }
-        self.update_len();
-    }
-    pub fn delete(&mut self, at: usize) {
-        if at >= self.len() {
-            return;
-        } else {
-            let mut result: String = self.string[..].graphemes(true).take(at).collect();
-            let remainder: String = self.string[..].graphemes(true).skip(at + 1).collect();
-            result.push_str(&remainder);
-            self.string = result;
+        if direction == SearchDirection::Backward {
+            return self.boundaries[..at]
+                .iter()
+                .rposition(|&index| self.string[index..].starts_with(query));
         }
+        let offset = self.byte_index(at);
+        let matching_byte_index = self.string[offset..].find(query)?;
+        //matches starting inside a grapheme do not count
+        self.boundaries.binary_search(&(offset + matching_byte_index)).ok()
     }
-    pub fn append(&mut self, next: Row) {
-        self.string = format!("{}{}", self.string, next.string);
-        self.update_len();
+    //grapheme ranges of every non-overlapping match of `query`
+    pub fn find_all(&self, query: &str) -> Vec<(usize, usize)> {
+        let query_len = query[..].graphemes(true).count();
+        let mut matches = Vec::new();
+        let mut at = 0;
+        while let Some(start) = self.find(query, at, SearchDirection::Forward) {
+            matches.push((start, start + query_len));
+            at = start + query_len;
+        }
+        matches
     }
-    pub fn split(&mut self, at: usize) -> Self {
-        let beginning: String = self.string[..].graphemes(true).take(at).collect();
-        let remainder: String = self.string[..].graphemes(true).skip(at).collect();
-        self.string = beginning;
-        self.update_len();
-        Self::from(&remainder[..])
+    //the graphemes from `start` up to `end`
+    pub fn substring(&self, start: usize, end: usize) -> String {
+        if end <= start {
+            return String::new();
+        }
+        self.string[self.byte_index(start)..self.byte_index(end)].to_string()
     }
-    pub fn as_bytes(&self) -> &[u8] {
-        self.string.as_bytes()
+    pub fn as_str(&self) -> &str {
+        &self.string
     }
-    fn update_len(&mut self) {
-        self.len = self.string[..].graphemes(true).count();
+    //converts between grapheme indices and byte offsets into the row
+    pub fn byte_index(&self, at: usize) -> usize {
+        self.boundaries.get(at).copied().unwrap_or(self.string.len())
     }
-}
\ No newline at end of file
+    pub fn grapheme_index(&self, byte_index: usize) -> usize {
+        self.boundaries.partition_point(|&index| index < byte_index)
+    }
+    //highlight class of the grapheme at `at`
+    pub fn highlight_at(&self, at: usize) -> highlighting::Type {
+        self.highlighting.get(at).copied().unwrap_or(highlighting::Type::None)
+    }
+    //recomputes the highlight class of every grapheme, `start_with_comment` tells
+    //whether the previous row left a multiline comment open.
+    //returns whether this row leaves one open for the next row
+    pub fn highlight(&mut self, opts: &HighlightingOptions, word: Option<&str>, start_with_comment: bool) -> bool {
+        let graphemes: Vec<&str> = self.graphemes().collect();
+        let mut highlighting = Vec::with_capacity(graphemes.len());
+        let mut in_comment = start_with_comment;
+        let mut index = 0;
+        while index < graphemes.len() {
+            let previous_is_separator = index == 0 || is_separator(graphemes[index - 1]);
+            let (length, hl_type) = if invalid_byte(graphemes[index]).is_some() {
+                (1, highlighting::Type::Invalid)
+            } else if in_comment {
+                if starts_with(&graphemes, index, "*/") {
+                    in_comment = false;
+                    (2, highlighting::Type::MultilineComment)
+                } else {
+                    (1, highlighting::Type::MultilineComment)
+                }
+            } else if opts.multiline_comments() && starts_with(&graphemes, index, "/*") {
+                in_comment = true;
+                (2, highlighting::Type::MultilineComment)
+            } else if opts.comments() && starts_with(&graphemes, index, "//") {
+                (graphemes.len() - index, highlighting::Type::Comment)
+            } else if opts.strings() && graphemes[index] == "\"" {
+                (string_len(&graphemes, index), highlighting::Type::String)
+            } else if let Some(length) = char_len(&graphemes, index).filter(|_| opts.characters()) {
+                (length, highlighting::Type::Character)
+            } else if opts.numbers() && previous_is_separator && is_digit(graphemes[index]) {
+                (number_len(&graphemes, index), highlighting::Type::Number)
+            } else if let Some(length) = keyword_len(&graphemes, index, opts.primary_keywords()).filter(|_| previous_is_separator) {
+                (length, highlighting::Type::PrimaryKeywords)
+            } else if let Some(length) = keyword_len(&graphemes, index, opts.secondary_keywords()).filter(|_| previous_is_separator) {
+                (length, highlighting::Type::SecondaryKeywords)
+            } else {
+                (1, highlighting::Type::None)
+            };
+            highlighting.extend(std::iter::repeat_n(hl_type, length));
+            index += length;
+        }
+        if let Some(word) = word {
+            for (start, end) in self.find_all(word) {
+                for hl_type in &mut highlighting[start..end] {
+                    *hl_type = highlighting::Type::Match;
+                }
+            }
+        }
+        self.highlighting = highlighting;
+        in_comment
+    }
+}
+//how many colums a grapheme takes, 0 for tabs. bytes that are not valid text are
+//shown as escapes, zero-width and control graphemes get a one colum placeholder
+//so the cursor stays in sync
+fn width(grapheme: &str) -> u8 {
+    if grapheme == "\t" {
+        return 0;
+    }
+    if invalid_byte(grapheme).is_some() {
+        return 4;
+    }
+    let width = grapheme.width();
+    if width == 0 || grapheme.chars().any(char::is_control) {
+        1
+    } else {
+        u8::try_from(width).unwrap_or(u8::MAX)
+    }
+}
+//what is drawn for a grapheme `width` colums wide
+fn glyph(grapheme: &str, width: usize) -> Cow<'_, str> {
+    if grapheme == "\t" {
+        return Cow::Owned(" ".repeat(width));
+    }
+    if let Some(byte) = invalid_byte(grapheme) {
+        return Cow::Owned(format!("\\x{byte:02X}"));
+    }
+    if grapheme.width() == 0 || grapheme.chars().any(char::is_control) {
+        Cow::Borrowed("\u{fffd}")
+    } else {
+        Cow::Borrowed(grapheme)
+    }
+}
+fn invalid_byte(grapheme: &str) -> Option<u8> {
+    grapheme.chars().next().and_then(escaped_byte)
+}
+fn is_separator(grapheme: &str) -> bool {
+    grapheme.chars().all(|c| c.is_ascii_punctuation() || c.is_whitespace())
+}
+fn is_digit(grapheme: &str) -> bool {
+    grapheme.chars().all(|c| c.is_ascii_digit())
+}
+fn starts_with(graphemes: &[&str], at: usize, pattern: &str) -> bool {
+    pattern[..]
+        .graphemes(true)
+        .enumerate()
+        .all(|(index, expected)| graphemes.get(at + index) == Some(&expected))
+}
+//a string runs until the closing quote or the end of the row
+fn string_len(graphemes: &[&str], at: usize) -> usize {
+    let mut index = at + 1;
+    while index < graphemes.len() {
+        match graphemes[index] {
+            "\\" => index += 2,
+            "\"" => return index + 1 - at,
+            _ => index += 1,
+        }
+    }
+    graphemes.len() - at
+}
+//only 'x' and '\x' count as character literals, so lifetimes stay unhighlighted
+fn char_len(graphemes: &[&str], at: usize) -> Option<usize> {
+    if graphemes[at] != "'" {
+        return None;
+    }
+    if graphemes.get(at + 1) == Some(&"\\") && graphemes.get(at + 3) == Some(&"'") {
+        Some(4)
+    } else if graphemes.get(at + 2) == Some(&"'") {
+        Some(3)
+    } else {
+        None
+    }
+}
+fn number_len(graphemes: &[&str], at: usize) -> usize {
+    graphemes[at..]
+        .iter()
+        .take_while(|grapheme| grapheme.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '_'))
+        .count()
+}
+fn keyword_len(graphemes: &[&str], at: usize, keywords: &[String]) -> Option<usize> {
+    keywords
+        .iter()
+        .filter(|keyword| starts_with(graphemes, at, keyword))
+        .map(|keyword| keyword[..].graphemes(true).count())
+        .find(|&length| graphemes.get(at + length).is_none_or(|grapheme| is_separator(grapheme)))
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn measures_colums() {
+        let row = Row::from("a\t界\u{10ff80}x");
+        assert_eq!(row.len(), 5);
+        assert_eq!(row.width_until(2, 4), 4);
+        assert_eq!(row.width_until(3, 4), 6);
+        assert_eq!(row.width_until(5, 4), 11);
+        assert_eq!(row.index_at_colum(2, 4), 1);
+        assert_eq!(row.index_at_colum(5, 4), 2);
+        assert_eq!(row.index_at_colum(9, 4), 3);
+        assert_eq!(row.index_at_colum(20, 4), 5);
+    }
+    #[test]
+    fn renders_tabs_escapes_and_cut_wide_graphemes() {
+        let row = Row::from("a\t界\u{10ff80}");
+        let text: String = row.render(0, 20, 4, &(0..0)).into_iter().map(|(_, _, text)| text).collect();
+        assert_eq!(text, "a   界\\x80");
+        let text: String = row.render(5, 20, 4, &(0..0)).into_iter().map(|(_, _, text)| text).collect();
+        assert_eq!(text, " \\x80");
+    }
+    #[test]
+    fn finds_forward_from_at() {
+        let row = Row::from("abc abc");
+        assert_eq!(row.find("abc", 0, SearchDirection::Forward), Some(0));
+        assert_eq!(row.find("abc", 1, SearchDirection::Forward), Some(4));
+        assert_eq!(row.find("abc", 5, SearchDirection::Forward), None);
+    }
+    #[test]
+    fn finds_backward_matches_running_past_at() {
+        let row = Row::from("abc abc");
+        assert_eq!(row.find("abc", 5, SearchDirection::Backward), Some(4));
+        assert_eq!(row.find("abc", 4, SearchDirection::Backward), Some(0));
+        assert_eq!(row.find("abc", 0, SearchDirection::Backward), None);
+    }
+    #[test]
+    fn ignores_matches_inside_graphemes() {
+        let row = Row::from("e\u{301}x");
+        assert_eq!(row.find("\u{301}", 0, SearchDirection::Forward), None);
+        assert_eq!(row.find("\u{301}", 2, SearchDirection::Backward), None);
+        assert_eq!(row.find("x", 2, SearchDirection::Backward), Some(1));
+    }
+}
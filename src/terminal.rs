@@ -1,37 +1,107 @@
-use std::io::{self, stdout, Write};
+use std::fs::File;
+use std::io::{self, stdout, IsTerminal, Write};
+use std::panic;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::thread;
 use crossterm::terminal::{
     Clear, ClearType,
+    EnterAlternateScreen, LeaveAlternateScreen,
+    enable_raw_mode, disable_raw_mode,
 };
 use crossterm::style::{
     SetBackgroundColor,
     SetForegroundColor,
+    SetAttribute, Attribute,
     Color, ResetColor,
 };
+use crossterm::event::{EnableBracketedPaste, DisableBracketedPaste};
 use crossterm::execute;
 use crate::Position;
 #[derive(Debug)]
 pub struct Size {
     pub colums: u16,
-    pub rows: u16,    
+    pub rows: u16,
 }
 pub struct Terminal {
     size: Size,
 }
+//whether the terminal is set up for the editor and still has to be restored
+static ENTERED: AtomicBool = AtomicBool::new(false);
+//puts the terminal back as it was when dropped, whether `Editor::run`
+//returns normally, with an error or unwinds from a panic
+pub struct Guard;
+impl Drop for Guard {
+    fn drop(&mut self) {
+        Terminal::leave();
+    }
+}
 impl Terminal {
-    pub fn default() -> io::Result<Self> {
+    //switches to raw mode on the alternate screen with bracketed paste, so pasted
+    //text arrives as a single event instead of one key per character. a panic hook
+    //restores the terminal before the panic message is printed, so it shows up
+    //readably on the normal screen. only panics of the entering thread do that, the
+    //editor keeps drawing after a background thread panicked
+    pub fn enter() -> io::Result<Guard> {
+        enable_raw_mode()?;
+        ENTERED.store(true, Ordering::SeqCst);
+        let guard = Guard;
+        execute!(stdout(), EnterAlternateScreen, Clear(ClearType::All), EnableBracketedPaste)?;
+        let default_hook = panic::take_hook();
+        let editor_thread = thread::current().id();
+        panic::set_hook(Box::new(move |info| {
+            if thread::current().id() == editor_thread {
+                Terminal::leave();
+            }
+            default_hook(info);
+        }));
+        Ok(guard)
+    }
+    //does nothing unless the terminal was entered, so the panic hook
+    //and the guard unwinding after it do not restore it twice
+    pub fn leave() {
+        if ENTERED.swap(false, Ordering::SeqCst) {
+            let _ = execute!(
+                stdout(),
+                ResetColor,
+                SetAttribute(Attribute::Reset),
+                crossterm::cursor::Show,
+                DisableBracketedPaste,
+                LeaveAlternateScreen,
+            );
+            let _ = disable_raw_mode();
+        }
+    }
+    //keys are read from standard input, or from the controlling terminal when input
+    //is piped in, which crossterm opens as /dev/tty then. without one there is
+    //nothing to read keys from
+    pub fn new() -> io::Result<Self> {
+        if cfg!(unix) && !io::stdin().is_terminal() {
+            File::open("/dev/tty")
+                .map_err(|error| io::Error::new(error.kind(), format!("no terminal to read keys from: {error}")))?;
+        }
         let size = crossterm::terminal::size()?;
-        Ok(Self {
-            size: Size { 
-                colums: size.0,
-                rows: size.1.saturating_sub(2),
-            },
-        })
+        let mut terminal = Self {
+            size: Size { colums: 0, rows: 0 },
+        };
+        terminal.resize(size.0, size.1);
+        Ok(terminal)
+    }
+    //the last two rows are kept for the status and message bars
+    pub fn resize(&mut self, colums: u16, rows: u16) {
+        self.size = Size {
+            colums,
+            rows: rows.saturating_sub(2),
+        };
     }
     pub fn size(&self) -> &Size {
         &self.size
     }
+    //only the alternate screen is ever cleared, the one the editor was started from
+    //and its scrollback are left as they were
     pub fn clear_screen() {
-        execute!(stdout(), Clear(ClearType::Purge), Clear(ClearType::All)).unwrap();
+        if ENTERED.load(Ordering::SeqCst) {
+            execute!(stdout(), Clear(ClearType::All)).unwrap();
+        }
     }
     pub fn clear_current_line() {
         execute!(stdout(), Clear(ClearType::CurrentLine)).unwrap();
@@ -45,10 +115,22 @@ impl Terminal {
     pub fn reset_color() {
         execute!(stdout(), ResetColor).unwrap();
     }
+    pub fn set_reverse() {
+        execute!(stdout(), SetAttribute(Attribute::Reverse)).unwrap();
+    }
+    pub fn reset_attributes() {
+        execute!(stdout(), SetAttribute(Attribute::Reset)).unwrap();
+    }
     #[allow(clippy::cast_possible_truncation)]
     pub fn move_cursor(position: &Position) {
         execute!(stdout(), crossterm::cursor::MoveTo(position.x as u16, position.y as u16)).unwrap();
     }
+    //OSC 52 asks the terminal emulator to put the text on the system clipboard,
+    //terminals without support ignore the sequence
+    pub fn set_clipboard(text: &str) {
+        print!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+        Terminal::flush().unwrap();
+    }
     pub fn flush() -> io::Result<()> {
         stdout().flush()
     }
@@ -59,3 +141,31 @@ impl Terminal {
         execute!(stdout(), crossterm::cursor::Hide).unwrap();
     }
 }
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let group = chunk.iter().enumerate().fold(0u32, |group, (index, &byte)| group | u32::from(byte) << (16 - 8 * index));
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(char::from(ALPHABET[(group >> (18 - 6 * index)) as usize & 63]));
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn encodes_base64_with_padding() {
+        assert_eq!(base64(b""), "");
+        assert_eq!(base64(b"f"), "Zg==");
+        assert_eq!(base64(b"fo"), "Zm8=");
+        assert_eq!(base64(b"foo"), "Zm9v");
+        assert_eq!(base64(b"foobar"), "Zm9vYmFy");
+        assert_eq!(base64(&[0xfb, 0xff, 0xfe]), "+//+");
+    }
+}
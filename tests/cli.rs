@@ -0,0 +1,27 @@
+use std::process::Command;
+fn hecto(arguments: &[&str]) -> (i32, String, String) {
+    let output = Command::new(env!("CARGO_BIN_EXE_hecto")).args(arguments).output().unwrap();
+    (
+        output.status.code().unwrap_or(-1),
+        String::from_utf8_lossy(&output.stdout).to_string(),
+        String::from_utf8_lossy(&output.stderr).to_string(),
+    )
+}
+#[test]
+fn prints_help_and_version() {
+    let (code, stdout, _) = hecto(&["--help"]);
+    assert_eq!(code, 0);
+    assert!(stdout.starts_with("Usage: hecto"));
+    let (code, stdout, _) = hecto(&["-V"]);
+    assert_eq!(code, 0);
+    assert_eq!(stdout, format!("hecto {}\n", env!("CARGO_PKG_VERSION")));
+}
+#[test]
+fn exits_with_2_on_bad_arguments() {
+    for arguments in [&["--bogus"][..], &["+x", "file"], &["file", "+3"]] {
+        let (code, stdout, stderr) = hecto(arguments);
+        assert_eq!(code, 2, "{arguments:?}");
+        assert!(stdout.is_empty());
+        assert!(stderr.starts_with("hecto: "), "{stderr}");
+    }
+}